@@ -1,13 +1,21 @@
 mod reports;
 
-use reports::{opening_report, blunder_report};
-use clap::Parser;
+use reports::{opening_report, blunder_report, blunder_profile_report, deviation_report, gap_report, lines_report, preparation_report, performance_report};
+use clap::{AppSettings, Parser, Subcommand};
+use hubble::analysis::gaps::GapOptions;
+use hubble::analysis::opening_tree::{common_moves, load_tree, start_ply, start_position, OpeningTree, TreeSource};
+use hubble::analysis::preparation::PreparationOptions;
+use hubble::analysis::stats::PerformanceGroup;
+use hubble_db::models::game_filter::{Color, GameFilter};
+use hubble_db::models::opening_name::OpeningLevel;
+use std::sync::Arc;
 
 #[derive(Parser, Debug)]
 #[clap(author, version, about, long_about = None)]
+#[clap(setting = AppSettings::SubcommandsNegateReqs)]
 struct Args {
-    #[clap(short, long)]
-    player: String,
+    #[clap(short, long, required = true)]
+    player: Option<String>,
 
     #[clap(long)]
     only_white: Option<bool>,
@@ -15,23 +23,443 @@ struct Args {
     #[clap(long)]
     opening_report: bool,
 
+    /// Groups the opening report by family, variation or sub_variation
+    #[clap(long, default_value = "sub_variation")]
+    opening_level: String,
+
+    /// Openings shown at each end of the opening report, all of them when not given
+    #[clap(long)]
+    opening_shown: Option<usize>,
+
+    #[clap(long, default_value_t = 10)]
+    num_games: usize,
+
+    /// Re-analyses games stored with an older analysis profile in a background process
+    #[clap(long)]
+    reanalyse: bool,
+
+    /// Runs --reanalyse in this process instead of a background one
+    #[clap(long)]
+    foreground: bool,
+
+    /// Stores the blunders of already analysed games so the blunder profile can group them by piece
+    #[clap(long)]
+    index_blunders: bool,
+
+    /// Recomputes the phases of already analysed games and regroups their blunders, without the engine
+    #[clap(long)]
+    recompute_phases: bool,
+
+    #[clap(long)]
+    blunder_profile: bool,
+
+    #[clap(long)]
+    classify_openings: bool,
+
+    #[clap(long)]
+    deviation_report: bool,
+
+    #[clap(long)]
+    export_repertoire: Option<String>,
+
+    #[clap(long, default_value_t = 20)]
+    depth: usize,
+
+    #[clap(long, default_value_t = 2)]
+    min_games: u32,
+
+    #[clap(long)]
+    gap_report: bool,
+
+    #[clap(long)]
+    reference: Option<String>,
+
+    /// Looks for puzzles at the player's blunders in their latest num_games stored games
+    #[clap(long)]
+    generate_puzzles: bool,
+
+    /// Writes the stored puzzles as pgn when the path ends in .pgn, as lichess csv otherwise
+    #[clap(long)]
+    export_puzzles: Option<String>,
+
+    /// Performance rating of the stored games by opening, color, time_control or month
+    #[clap(long)]
+    performance: Option<String>,
+
+    #[clap(subcommand)]
+    command: Option<Command>,
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Most popular continuations at every ply from a position
+    Lines(LinesArgs),
+    /// Fetches an opponent's latest lichess games and reports their lines, worst openings and blunders
+    Prepare(PrepareArgs),
+}
+
+#[derive(clap::Args, Debug)]
+struct LinesArgs {
+    /// Pgn file to read the games from
+    #[clap(long)]
+    file: Option<String>,
+
+    /// Lichess player to fetch the games of
+    #[clap(long)]
+    lichess: Option<String>,
+
+    /// Stored games of this player, used when no file or lichess player is given
+    #[clap(long)]
+    db_player: Option<String>,
+
+    #[clap(long)]
+    color: Option<String>,
+
+    #[clap(long)]
+    eco: Option<String>,
+
+    #[clap(long)]
+    time_control: Option<String>,
+
+    #[clap(long, default_value_t = 1000)]
+    max_games: usize,
+
+    /// Drops moves played fewer times than this while reading, keeps memory bounded on big dumps
+    #[clap(long, default_value_t = 1)]
+    min_count: u32,
+
+    #[clap(long)]
+    fen: Option<String>,
+
+    /// Moves from the start position, uci or san separated by spaces
+    #[clap(long)]
+    moves: Option<String>,
+
     #[clap(long, default_value_t = 10)]
-    num_games: usize
+    depth: usize,
+
+    #[clap(long, default_value_t = 3)]
+    top: usize,
+}
+
+#[derive(clap::Args, Debug)]
+struct PrepareArgs {
+    /// Lichess player to prepare against
+    opponent: String,
+
+    #[clap(long, default_value_t = 50)]
+    num_games: usize,
+
+    /// Plies of the frequent lines
+    #[clap(long, default_value_t = 8)]
+    depth: usize,
 }
 
+async fn popular_lines(args: LinesArgs) {
+    let moves = args
+        .moves
+        .as_deref()
+        .map(|moves| moves.split_whitespace().map(str::to_string).collect::<Vec<_>>())
+        .unwrap_or_default();
+    let start = match start_position(args.fen.as_deref(), &moves) {
+        Ok(start) => start,
+        Err(e) => {
+            println!("{}", e);
+            return;
+        }
+    };
+
+    let mut db = None;
+    let source = if let Some(path) = args.file {
+        TreeSource::File(path)
+    } else if let Some(player) = args.lichess {
+        TreeSource::Lichess {
+            player,
+            games: args.max_games,
+        }
+    } else {
+        let color = match args.color.as_deref().map(str::parse::<Color>) {
+            Some(Ok(color)) => Some(color),
+            Some(Err(_)) => {
+                println!("color should be white or black");
+                return;
+            }
+            None => None,
+        };
+        match hubble_db::establish_connection() {
+            Ok(pool) => db = Some(hubble_db::Database::new(pool)),
+            Err(e) => {
+                println!("{}", e);
+                return;
+            }
+        }
+        TreeSource::Database(GameFilter {
+            player: args.db_player,
+            color,
+            eco: args.eco,
+            time_control: args.time_control,
+            limit: args.max_games as i64,
+            ..GameFilter::default()
+        })
+    };
+
+    match load_tree(db.as_ref(), source, start_ply(&start) + args.depth, args.min_count).await {
+        Ok(tree) => {
+            let plies = common_moves(&tree, &start, args.depth, args.top);
+            let report = lines_report(&plies);
+            println!("{} games", tree.games());
+            println!("{report}");
+        }
+        Err(e) => println!("{}", e),
+    }
+}
+
+
+
+async fn prepare_opponent(args: PrepareArgs) {
+    let db = match hubble_db::establish_connection() {
+        Ok(pool) => hubble_db::Database::new(pool),
+        Err(e) => {
+            println!("{}", e);
+            return;
+        }
+    };
+    let classifier = match hubble::analysis::opening::load_classifier(&db).await {
+        Ok(classifier) => Arc::new(classifier),
+        Err(e) => {
+            println!("{}", e);
+            return;
+        }
+    };
+
+    let fetched =
+        hubble::lichess::analyse_player(&db, &classifier, &args.opponent, args.num_games).await;
+    if let Err(e) = fetched {
+        println!("{:?}", e);
+        return;
+    }
+    let options = PreparationOptions {
+        line_depth: args.depth,
+        ..PreparationOptions::default()
+    };
+    let report = hubble::analysis::preparation::prepare(
+        &db,
+        &classifier,
+        &args.opponent,
+        args.num_games as i64,
+        options,
+    )
+    .await;
+    match report {
+        Ok(report) => println!("{}", preparation_report(&report)),
+        Err(e) => println!("{}", e),
+    }
+}
 
+fn reanalyse_in_background(player: &str) {
+    //A detached copy of the cli does the work, so the shell is free while the engine runs
+    let log_path = format!("reanalyse-{}.log", player);
+    let spawned = std::env::current_exe().and_then(|exe| {
+        let log = std::fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&log_path)?;
+        std::process::Command::new(exe)
+            .args(["--player", player, "--reanalyse", "--foreground"])
+            .stdin(std::process::Stdio::null())
+            .stdout(log.try_clone()?)
+            .stderr(log)
+            .spawn()
+    });
+    match spawned {
+        Ok(child) => println!(
+            "Re-analysing stale games of {} in the background (pid {}), logging to {}",
+            player,
+            child.id(),
+            log_path
+        ),
+        Err(e) => println!("{}", e),
+    }
+}
 
 #[tokio::main]
 async fn main() {
     dotenv::from_filename("../.env").ok();
     let args = Args::parse();
-    let pool = hubble_db::establish_connection();
-    let conn = pool.get().unwrap();
+    match args.command {
+        Some(Command::Lines(lines)) => {
+            popular_lines(lines).await;
+            return;
+        }
+        Some(Command::Prepare(prepare)) => {
+            prepare_opponent(prepare).await;
+            return;
+        }
+        None => {}
+    }
+
+    let player = args.player.as_deref().unwrap_or_default();
+    if args.reanalyse && !args.foreground {
+        reanalyse_in_background(player);
+        return;
+    }
+    let db = match hubble_db::establish_connection() {
+        Ok(pool) => hubble_db::Database::new(pool),
+        Err(e) => {
+            println!("{}", e);
+            return;
+        }
+    };
+
+    let classifier = match hubble::analysis::opening::load_classifier(&db).await {
+        Ok(classifier) => Arc::new(classifier),
+        Err(e) => {
+            println!("{}", e);
+            return;
+        }
+    };
 
-    if args.opening_report {
-        match hubble::analysis::best_opening(&args.player, &conn, 1000, args.only_white).await {
+    if args.classify_openings {
+        match hubble::analysis::opening::classify_stored_games(&db, &classifier, 500).await {
+            Ok(n) => println!("Classified {} games", n),
+            Err(e) => println!("{}", e),
+        }
+    } else if args.deviation_report {
+        match db.get_deviation_stats(player, true).await {
+            Ok(stats) => {
+                let report = deviation_report(stats);
+                println!("{report}");
+            }
+            Err(e) => println!("{}", e),
+        }
+    } else if let Some(path) = &args.export_repertoire {
+        let color = match args.only_white {
+            Some(false) => Color::Black,
+            _ => Color::White,
+        };
+        let repertoire =
+            hubble::analysis::repertoire::player_repertoire(&db, player, color, args.depth, 1000)
+                .await;
+        match repertoire {
+            Ok(repertoire) => {
+                let pgn = hubble::pgn::repertoire_to_pgn(&repertoire, args.min_games, args.depth);
+                match std::fs::write(path, pgn) {
+                    Ok(_) => println!("Wrote repertoire of {} games to {}", repertoire.games, path),
+                    Err(e) => println!("{}", e),
+                }
+            }
+            Err(e) => println!("{}", e),
+        }
+    } else if args.gap_report {
+        let color = match args.only_white {
+            Some(false) => Color::Black,
+            _ => Color::White,
+        };
+        let reference = args
+            .reference
+            .as_deref()
+            .map(|path| OpeningTree::from_pgn_file(path, args.depth, 1));
+        let reference = match reference {
+            Some(Ok(tree)) => Some(tree),
+            Some(Err(e)) => {
+                println!("{}", e);
+                return;
+            }
+            None => None,
+        };
+        let options = GapOptions {
+            max_ply: args.depth,
+            ..GapOptions::default()
+        };
+        let gaps =
+            hubble::analysis::gaps::player_gaps(&db, player, color, reference.as_ref(), options)
+                .await;
+        match gaps {
+            Ok(gaps) => {
+                let report = gap_report(gaps);
+                println!("{report}");
+            }
+            Err(e) => println!("{}", e),
+        }
+    } else if let Some(group) = &args.performance {
+        let group = match group.parse::<PerformanceGroup>() {
+            Ok(group) => group,
+            Err(_) => {
+                println!("performance should be opening, color, time_control or month");
+                return;
+            }
+        };
+        match hubble::analysis::stats::stored_performance(&db, player, group, 10_000).await {
+            Ok(performances) => {
+                let report = performance_report(performances);
+                println!("{report}");
+            }
+            Err(e) => println!("{}", e),
+        }
+    } else if args.index_blunders {
+        match hubble::analysis::blunder::index_stored_blunders(&db, Some(player), 500).await {
+            Ok(n) => println!("Indexed {} blunders", n),
+            Err(e) => println!("{}", e),
+        }
+    } else if args.recompute_phases {
+        let options = hubble::analysis::phase::PhaseOptions::default();
+        match hubble::analysis::phase::recompute_phases(&db, Some(player), 500, &options).await {
+            Ok(n) => println!("Recomputed phases of {} games", n),
+            Err(e) => println!("{}", e),
+        }
+    } else if args.blunder_profile {
+        match db.get_blunder_profile(player).await {
+            Ok(profile) => println!("{}", blunder_profile_report(&profile)),
+            Err(e) => println!("{}", e),
+        }
+    } else if args.generate_puzzles {
+        if !hubble::analysis::engine_available() {
+            println!("No engine at {}", hubble::analysis::ENGINE_PATH);
+            return;
+        }
+        let generated =
+            hubble::analysis::puzzle::generate_puzzles(&db, player, args.num_games as i64).await;
+        match generated {
+            Ok(n) => println!("Stored {} puzzles", n),
+            Err(e) => println!("{}", e),
+        }
+    } else if let Some(path) = &args.export_puzzles {
+        match db.get_puzzles(player).await {
+            Ok(puzzles) => {
+                let export = if path.ends_with(".pgn") {
+                    hubble::pgn::puzzles_to_pgn(&puzzles)
+                } else {
+                    hubble::analysis::puzzle::puzzles_to_csv(&puzzles)
+                };
+                match std::fs::write(path, export) {
+                    Ok(_) => println!("Wrote {} puzzles to {}", puzzles.len(), path),
+                    Err(e) => println!("{}", e),
+                }
+            }
+            Err(e) => println!("{}", e),
+        }
+    } else if args.reanalyse {
+        let reanalysed =
+            hubble::lichess::reanalyse_stale_games(&db, &classifier, Some(player), 50).await;
+        match reanalysed {
+            Ok(games) => {
+                println!("Re-analysed {} games", games.len());
+                let report = blunder_report(games);
+                println!("{report}");
+            }
+            Err(e) => println!("{:?}", e),
+        }
+    } else if args.opening_report {
+        let level = match args.opening_level.parse::<OpeningLevel>() {
+            Ok(level) => level,
+            Err(_) => {
+                println!("opening level should be family, variation or sub_variation");
+                return;
+            }
+        };
+        match hubble::analysis::best_opening(player, &db, 1000, args.only_white, level).await {
             Ok(mut opening_count) => {
-                let report = opening_report(&mut opening_count);
+                let report = opening_report(&mut opening_count, args.opening_shown);
                 println!("{report}");
             }
             Err(_) => {
@@ -39,7 +467,7 @@ async fn main() {
             }
         }
     } else {
-        match hubble::lichess::analyse_player(conn, &args.player, args.num_games).await {
+        match hubble::lichess::analyse_player(&db, &classifier, player, args.num_games).await {
             Ok(games) => {
                 let report = blunder_report(games);
                 println!("{report}");
@@ -0,0 +1,36 @@
+use comfy_table::Table;
+use hubble_db::models::blunder_stats::{BlunderRate, PlayerBlunderProfile};
+
+fn rate_table(title: &str, rates: &[BlunderRate]) -> Table {
+    let mut table = Table::new();
+    table.set_header(vec![title, "moves", "blunders", "per 100 moves"]);
+    for rate in rates {
+        table.add_row(vec![
+            rate.key.clone(),
+            rate.moves.to_string(),
+            rate.blunders.to_string(),
+            format!("{:.1}", rate.per_100_moves),
+        ]);
+    }
+    table
+}
+
+pub fn blunder_profile_report(profile: &PlayerBlunderProfile) -> String {
+    let mut out = format!(
+        "Blunders of {}: {} in {} moves, {:.1} per 100 moves\n",
+        profile.player, profile.blunders, profile.moves, profile.per_100_moves
+    );
+
+    for (title, rates) in [
+        ("phase", &profile.by_phase),
+        ("color", &profile.by_color),
+        ("piece", &profile.by_piece),
+        ("motif", &profile.by_motif),
+        ("position before", &profile.by_situation),
+        ("month", &profile.by_month),
+    ] {
+        out.push_str(&format!("{}\n", rate_table(title, rates)));
+    }
+
+    out
+}
@@ -0,0 +1,38 @@
+use comfy_table::Table;
+use hubble_db::models::opening_stats::DeviationStat;
+
+fn format_float(input: Option<f64>) -> String {
+    match input {
+        Some(a) => format!("{:.1}", a),
+        None => String::from(" "),
+    }
+}
+
+pub fn deviation_report(stats: Vec<DeviationStat>) -> Table {
+    let mut table = Table::new();
+    table.set_header(vec![
+        "eco",
+        "opening",
+        "games",
+        "avg ply left book",
+        "avg eval swing",
+        "won",
+        "tie",
+        "lost",
+    ]);
+
+    for stat in stats {
+        table.add_row(vec![
+            stat.eco.unwrap_or_default(),
+            stat.name.unwrap_or_else(|| String::from("Unknown")),
+            stat.games.to_string(),
+            format_float(stat.avg_ply),
+            format_float(stat.avg_swing),
+            stat.won.to_string(),
+            stat.drawn.to_string(),
+            stat.lost.to_string(),
+        ]);
+    }
+
+    table
+}
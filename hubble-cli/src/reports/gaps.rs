@@ -0,0 +1,51 @@
+use comfy_table::Table;
+use hubble::analysis::gaps::{GapReason, RepertoireGap};
+
+fn format_float(input: Option<f64>) -> String {
+    match input {
+        Some(a) => format!("{:.2}", a),
+        None => String::from(" "),
+    }
+}
+
+fn reason_name(reason: &GapReason) -> &'static str {
+    match reason {
+        GapReason::NeverFaced => "never faced",
+        GapReason::FewGames => "few games",
+        GapReason::LowScore => "low score",
+        GapReason::EvalDrop => "eval drop",
+    }
+}
+
+pub fn gap_report(gaps: Vec<RepertoireGap>) -> Table {
+    let mut table = Table::new();
+    table.set_header(vec![
+        "line",
+        "reply",
+        "frequency",
+        "games",
+        "score",
+        "usual response",
+        "eval drop",
+        "reason",
+    ]);
+
+    for gap in gaps.iter().take(20) {
+        table.add_row(vec![
+            gap.line.join(" "),
+            gap.reply.clone(),
+            format!("{:.1}%", gap.frequency * 100.),
+            gap.player_games.to_string(),
+            format_float(gap.score),
+            gap.usual_response.clone().unwrap_or_default(),
+            format_float(gap.eval_drop.map(|drop| drop / 100.)),
+            gap.reasons
+                .iter()
+                .map(reason_name)
+                .collect::<Vec<_>>()
+                .join(", "),
+        ]);
+    }
+
+    table
+}
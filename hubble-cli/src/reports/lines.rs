@@ -0,0 +1,43 @@
+use comfy_table::Table;
+use hubble::analysis::opening_tree::PlyContinuations;
+
+fn percent(part: u32, total: u32) -> String {
+    format!("{:.0}%", part as f64 * 100. / total as f64)
+}
+
+pub fn lines_report(plies: &[PlyContinuations]) -> Table {
+    let mut table = Table::new();
+    table.set_header(vec![
+        "ply",
+        "after",
+        "move",
+        "games",
+        "frequency",
+        "white",
+        "draw",
+        "black",
+    ]);
+
+    for ply in plies {
+        for (idx, mv) in ply.moves.iter().enumerate() {
+            //The line is only printed on the first row of each ply
+            let (number, after) = if idx == 0 {
+                (ply.ply.to_string(), ply.line.join(" "))
+            } else {
+                (String::new(), String::new())
+            };
+            table.add_row(vec![
+                number,
+                after,
+                mv.san.clone(),
+                mv.games.to_string(),
+                format!("{:.1}%", mv.frequency * 100.),
+                percent(mv.white, mv.games),
+                percent(mv.draws, mv.games),
+                percent(mv.black, mv.games),
+            ]);
+        }
+    }
+
+    table
+}
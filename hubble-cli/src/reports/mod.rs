@@ -1,5 +1,17 @@
 mod opening;
 mod blunder;
+mod blunder_profile;
+mod deviation;
+mod gaps;
+mod lines;
+mod preparation;
+mod performance;
 
 pub use opening::opening_report;
 pub use blunder::blunder_report;
+pub use blunder_profile::blunder_profile_report;
+pub use deviation::deviation_report;
+pub use gaps::gap_report;
+pub use lines::lines_report;
+pub use preparation::preparation_report;
+pub use performance::performance_report;
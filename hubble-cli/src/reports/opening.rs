@@ -1,43 +1,75 @@
 use comfy_table::Table;
 use hubble::analysis::OpeningResult;
 
-pub fn opening_report(count: &mut Vec<(String, OpeningResult)>) -> Table{
-    let mut count_win_rate = count
-        .iter()
-        .filter_map(|row| {
-            let tot = (row.1.won + row.1.lost + row.1.tie) as f64;
-            if tot < 5. {
-                None
-            } else {
-                Some((row.0.clone(), row.1, row.1.won as f64 / tot))
-            }
-        })
-        .collect::<Vec<(String, OpeningResult, f64)>>();
+fn verdict(result: &OpeningResult) -> &'static str {
+    //Only calls it when the expected score is outside the confidence interval
+    let (lower, upper) = result.interval();
+    match result.expected_score() {
+        Some(expected) if lower > expected => "over",
+        Some(expected) if upper < expected => "under",
+        Some(_) => "",
+        None => "?",
+    }
+}
+
+fn add_row(table: &mut Table, name: &str, op: &OpeningResult) {
+    let (lower, upper) = op.interval();
+    let expected = op.expected_score();
 
-    count_win_rate.sort_by(|a, b| b.2.partial_cmp(&a.2).unwrap());
+    table.add_row(vec![
+        name.to_string(),
+        op.total().to_string(),
+        op.won.to_string(),
+        op.tie.to_string(),
+        op.lost.to_string(),
+        format!("{:.2}", op.score()),
+        format!("{:.2}-{:.2}", lower, upper),
+        expected.map_or(String::from("-"), |e| format!("{:.2}", e)),
+        expected.map_or(String::from("-"), |e| format!("{:+.2}", op.score() - e)),
+        verdict(op).to_string(),
+    ]);
+}
+
+/// Openings ranked by the lower bound of their score interval, so a few lucky games
+/// do not outrank a long record. Best openings first, the worst ones at the bottom.
+/// With `shown`, only that many openings are kept at each end of the ranking.
+pub fn opening_report(count: &mut [(String, OpeningResult)], shown: Option<usize>) -> Table {
+    count.sort_by(|a, b| b.1.interval().0.total_cmp(&a.1.interval().0));
     let mut table = Table::new();
 
-    table.set_header(vec!["name", "won", "tie", "lost", "win rate"]);
+    table.set_header(vec![
+        "name",
+        "games",
+        "won",
+        "tie",
+        "lost",
+        "score",
+        "95% interval",
+        "expected",
+        "+/-",
+        "verdict",
+    ]);
+
+    let shown = match shown {
+        Some(shown) if count.len() > 2 * shown => shown,
+        _ => {
+            for (name, op) in count.iter() {
+                add_row(&mut table, name, op);
+            }
+            return table;
+        }
+    };
 
-    for (name, op, win_rate) in count_win_rate.iter().take(10) {
-        table.add_row(vec![
-            name,
-            &op.won.to_string(),
-            &op.tie.to_string(),
-            &op.lost.to_string(),
-            &win_rate.to_string(),
-        ]);
+    for (name, op) in count.iter().take(shown) {
+        add_row(&mut table, name, op);
     }
+    table.add_row(vec!["..."; 10]);
 
-    table.add_row(vec!["...", "...", "...", "..."]);
-    for (name, op, win_rate) in count_win_rate.iter().rev().take(10).rev() {
-        table.add_row(vec![
-            name,
-            &op.won.to_string(),
-            &op.tie.to_string(),
-            &op.lost.to_string(),
-            &win_rate.to_string(),
-        ]);
+    //The worst are the ones most surely bad, lowest upper bound last
+    let mut worst = count[shown..].iter().collect::<Vec<_>>();
+    worst.sort_by(|a, b| b.1.interval().1.total_cmp(&a.1.interval().1));
+    for (name, op) in worst.iter().rev().take(shown).rev() {
+        add_row(&mut table, name, op);
     }
 
     table
@@ -0,0 +1,40 @@
+use comfy_table::Table;
+use hubble::analysis::stats::Performance;
+
+fn format_float(input: Option<f64>, precision: usize) -> String {
+    match input {
+        Some(a) => format!("{:.*}", precision, a),
+        None => String::from(" "),
+    }
+}
+
+pub fn performance_report(performances: Vec<Performance>) -> Table {
+    let mut table = Table::new();
+    table.set_header(vec![
+        "group",
+        "games",
+        "won",
+        "tie",
+        "lost",
+        "score",
+        "expected",
+        "avg opponent",
+        "performance",
+    ]);
+
+    for performance in performances {
+        table.add_row(vec![
+            performance.group,
+            performance.games.to_string(),
+            performance.won.to_string(),
+            performance.drawn.to_string(),
+            performance.lost.to_string(),
+            format!("{:.2}", performance.score),
+            format_float(performance.expected_score, 2),
+            format_float(performance.average_opponent, 0),
+            format_float(performance.performance, 0),
+        ]);
+    }
+
+    table
+}
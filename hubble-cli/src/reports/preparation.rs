@@ -0,0 +1,70 @@
+use comfy_table::Table;
+use hubble::analysis::preparation::{BlunderPhase, ColorPreparation, PreparationReport};
+
+fn phase_name(phase: &BlunderPhase) -> &'static str {
+    match phase {
+        BlunderPhase::Opening => "opening",
+        BlunderPhase::MiddleGame => "middle game",
+        BlunderPhase::EndGame => "end game",
+    }
+}
+
+fn color_tables(prep: &ColorPreparation) -> (Table, Table) {
+    let mut lines = Table::new();
+    lines.set_header(vec!["line", "games"]);
+    for line in &prep.lines {
+        lines.add_row(vec![line.moves.join(" "), line.games.to_string()]);
+    }
+
+    let mut openings = Table::new();
+    openings.set_header(vec!["worst openings", "won", "tie", "lost", "score"]);
+    for opening in &prep.worst_openings {
+        openings.add_row(vec![
+            opening.name.clone(),
+            opening.won.to_string(),
+            opening.drawn.to_string(),
+            opening.lost.to_string(),
+            format!("{:.2}", opening.score),
+        ]);
+    }
+
+    (lines, openings)
+}
+
+pub fn preparation_report(report: &PreparationReport) -> String {
+    let mut out = format!(
+        "Preparation for {} ({} games)\n",
+        report.opponent, report.games
+    );
+
+    for (title, prep) in [("As white", &report.white), ("As black", &report.black)] {
+        let (lines, openings) = color_tables(prep);
+        out.push_str(&format!(
+            "\n{} ({} games)\n{}\n{}\n",
+            title, prep.games, lines, openings
+        ));
+    }
+
+    let mut blunders = Table::new();
+    blunders.set_header(vec![
+        "blunders opening",
+        "blunders middle game",
+        "blunders end game",
+        "typical phase",
+    ]);
+    blunders.add_row(vec![
+        report.blunders.opening.to_string(),
+        report.blunders.middle_game.to_string(),
+        report.blunders.end_game.to_string(),
+        report
+            .blunders
+            .typical
+            .as_ref()
+            .map(phase_name)
+            .unwrap_or("none")
+            .to_string(),
+    ]);
+    out.push_str(&format!("\n{}\n", blunders));
+
+    out
+}
@@ -0,0 +1,3 @@
+-- Your SQL goes here
+ALTER TABLE games
+  ADD COLUMN analysis_version INTEGER NOT NULL DEFAULT 0;
@@ -0,0 +1,6 @@
+-- This file should undo anything in `up.sql`
+
+ALTER TABLE games
+  DROP COLUMN engine,
+  DROP COLUMN engine_nodes,
+  DROP COLUMN analysed_at;
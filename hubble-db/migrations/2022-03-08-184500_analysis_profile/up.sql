@@ -0,0 +1,5 @@
+-- Your SQL goes here
+ALTER TABLE games
+  ADD COLUMN engine VARCHAR,
+  ADD COLUMN engine_nodes INTEGER,
+  ADD COLUMN analysed_at TIMESTAMP;
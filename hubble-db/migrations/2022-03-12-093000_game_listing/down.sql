@@ -0,0 +1,10 @@
+-- This file should undo anything in `up.sql`
+
+DROP INDEX games_white_idx;
+DROP INDEX games_black_idx;
+DROP INDEX games_played_at_idx;
+
+ALTER TABLE games
+  DROP COLUMN played_at,
+  DROP COLUMN time_control,
+  DROP COLUMN speed;
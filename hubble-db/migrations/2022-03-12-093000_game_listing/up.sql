@@ -0,0 +1,9 @@
+-- Your SQL goes here
+ALTER TABLE games
+  ADD COLUMN played_at TIMESTAMP,
+  ADD COLUMN time_control VARCHAR,
+  ADD COLUMN speed VARCHAR;
+
+CREATE INDEX games_white_idx ON games (white);
+CREATE INDEX games_black_idx ON games (black);
+CREATE INDEX games_played_at_idx ON games (played_at);
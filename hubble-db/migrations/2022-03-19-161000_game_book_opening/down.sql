@@ -0,0 +1,7 @@
+-- This file should undo anything in `up.sql`
+
+DROP INDEX games_book_opening_id_idx;
+
+ALTER TABLE games
+  DROP COLUMN book_opening_id,
+  DROP COLUMN left_book_ply;
@@ -0,0 +1,6 @@
+-- Your SQL goes here
+ALTER TABLE games
+  ADD COLUMN book_opening_id INTEGER REFERENCES openings(id) ON DELETE SET NULL,
+  ADD COLUMN left_book_ply INTEGER;
+
+CREATE INDEX games_book_opening_id_idx ON games (book_opening_id);
@@ -0,0 +1,5 @@
+-- This file should undo anything in `up.sql`
+
+ALTER TABLE games
+  DROP COLUMN left_book_by,
+  DROP COLUMN book_exit_swing;
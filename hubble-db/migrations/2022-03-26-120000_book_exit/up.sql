@@ -0,0 +1,4 @@
+-- Your SQL goes here
+ALTER TABLE games
+  ADD COLUMN left_book_by VARCHAR,
+  ADD COLUMN book_exit_swing INTEGER;
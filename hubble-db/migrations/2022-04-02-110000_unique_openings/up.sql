@@ -0,0 +1,18 @@
+-- Your SQL goes here
+-- Point games at the first copy of every duplicated opening before removing the rest
+WITH duplicates AS (
+  SELECT id, MIN(id) OVER (PARTITION BY eco, name, pgn) AS keep_id
+  FROM openings
+)
+UPDATE games SET book_opening_id = duplicates.keep_id
+FROM duplicates
+WHERE games.book_opening_id = duplicates.id AND duplicates.id <> duplicates.keep_id;
+
+DELETE FROM openings a
+  USING openings b
+  WHERE a.id > b.id AND a.eco = b.eco AND a.name = b.name AND a.pgn = b.pgn;
+
+CREATE UNIQUE INDEX openings_eco_name_pgn ON openings (eco, name, pgn);
+
+-- Ids used to be set by the importer, move the sequence past them
+SELECT setval(pg_get_serial_sequence('openings', 'id'), COALESCE(MAX(id), 0) + 1, false) FROM openings;
@@ -0,0 +1,7 @@
+-- This file should undo anything in `up.sql`
+DROP INDEX openings_epd;
+
+ALTER TABLE openings
+  DROP COLUMN uci,
+  DROP COLUMN epd,
+  DROP COLUMN ply;
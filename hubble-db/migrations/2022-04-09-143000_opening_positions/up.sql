@@ -0,0 +1,7 @@
+-- Your SQL goes here
+ALTER TABLE openings
+  ADD COLUMN uci VARCHAR,
+  ADD COLUMN epd VARCHAR,
+  ADD COLUMN ply INTEGER;
+
+CREATE INDEX openings_epd ON openings (epd);
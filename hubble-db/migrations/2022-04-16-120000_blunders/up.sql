@@ -0,0 +1,9 @@
+-- Your SQL goes here
+CREATE TABLE blunders (
+  game_id VARCHAR NOT NULL REFERENCES games(id) ON DELETE CASCADE,
+  ply INTEGER NOT NULL,
+  uci VARCHAR NOT NULL,
+  san VARCHAR NOT NULL,
+  piece VARCHAR NOT NULL,
+  PRIMARY KEY (game_id, ply)
+);
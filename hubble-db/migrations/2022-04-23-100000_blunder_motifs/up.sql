@@ -0,0 +1,3 @@
+-- Your SQL goes here
+ALTER TABLE blunders
+  ADD COLUMN motifs VARCHAR[] NOT NULL DEFAULT '{}';
@@ -0,0 +1,18 @@
+-- Your SQL goes here
+CREATE TABLE puzzles (
+  game_id VARCHAR NOT NULL REFERENCES games(id) ON DELETE CASCADE,
+  ply INTEGER NOT NULL,
+  player VARCHAR NOT NULL,
+  fen VARCHAR NOT NULL,
+  previous_fen VARCHAR,
+  previous_move VARCHAR,
+  solution VARCHAR[] NOT NULL,
+  played VARCHAR NOT NULL,
+  best_score INTEGER NOT NULL,
+  second_score INTEGER,
+  themes VARCHAR[] NOT NULL DEFAULT '{}',
+  rating INTEGER,
+  PRIMARY KEY (game_id, ply)
+);
+
+CREATE INDEX puzzles_player_idx ON puzzles (player);
@@ -0,0 +1,25 @@
+-- Your SQL goes here
+CREATE TABLE training_items (
+  id SERIAL PRIMARY KEY,
+  player VARCHAR NOT NULL,
+  kind VARCHAR NOT NULL,
+  fen VARCHAR NOT NULL,
+  answers VARCHAR[] NOT NULL,
+  game_id VARCHAR REFERENCES games(id) ON DELETE CASCADE,
+  repetitions INTEGER NOT NULL DEFAULT 0,
+  interval_days INTEGER NOT NULL DEFAULT 0,
+  ease DOUBLE PRECISION NOT NULL DEFAULT 2.5,
+  due_at TIMESTAMP NOT NULL DEFAULT now()
+);
+
+CREATE UNIQUE INDEX training_items_player_kind_fen ON training_items (player, kind, fen);
+CREATE INDEX training_items_due_at_idx ON training_items (player, due_at);
+
+CREATE TABLE training_attempts (
+  id SERIAL PRIMARY KEY,
+  item_id INTEGER NOT NULL REFERENCES training_items(id) ON DELETE CASCADE,
+  submitted VARCHAR NOT NULL,
+  correct BOOLEAN NOT NULL,
+  time_ms INTEGER NOT NULL,
+  attempted_at TIMESTAMP NOT NULL DEFAULT now()
+);
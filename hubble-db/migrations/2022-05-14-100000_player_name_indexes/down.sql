@@ -0,0 +1,6 @@
+-- This file should undo anything in `up.sql`
+DROP INDEX games_white_idx;
+DROP INDEX games_black_idx;
+
+CREATE INDEX games_white_idx ON games (white);
+CREATE INDEX games_black_idx ON games (black);
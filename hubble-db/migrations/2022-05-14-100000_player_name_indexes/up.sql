@@ -0,0 +1,6 @@
+-- Your SQL goes here
+DROP INDEX games_white_idx;
+DROP INDEX games_black_idx;
+
+CREATE INDEX games_white_idx ON games (lower(white));
+CREATE INDEX games_black_idx ON games (lower(black));
@@ -0,0 +1,3 @@
+-- This file should undo anything in `up.sql`
+-- The original spelling of the names is lost, lowercased names still match
+SELECT 1;
@@ -0,0 +1,2 @@
+-- Your SQL goes here
+UPDATE puzzles SET player = lower(player);
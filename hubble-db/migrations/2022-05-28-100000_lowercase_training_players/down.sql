@@ -0,0 +1,3 @@
+-- This file should undo anything in `up.sql`
+-- The original spelling of the names and the merged items are lost, lowercased names still match
+SELECT 1;
@@ -0,0 +1,20 @@
+-- Your SQL goes here
+-- Items synced under several spellings of a name become one, the most practised schedule
+-- is kept together with the attempts of all of them
+CREATE TEMPORARY TABLE training_item_keep AS
+  SELECT id, first_value(id) OVER (
+    PARTITION BY lower(player), kind, fen ORDER BY repetitions DESC, id
+  ) AS keep_id
+  FROM training_items;
+
+UPDATE training_attempts a SET item_id = k.keep_id
+  FROM training_item_keep k
+  WHERE a.item_id = k.id AND k.id <> k.keep_id;
+
+DELETE FROM training_items t
+  USING training_item_keep k
+  WHERE t.id = k.id AND k.id <> k.keep_id;
+
+DROP TABLE training_item_keep;
+
+UPDATE training_items SET player = lower(player);
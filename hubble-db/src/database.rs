@@ -0,0 +1,254 @@
+use chrono::NaiveDateTime;
+use diesel::pg::PgConnection;
+use diesel::r2d2::PoolError;
+use diesel::Connection;
+use std::fmt;
+
+use crate::db::PgPool;
+use crate::models::blunder::{self, Blunder};
+use crate::models::blunder_stats::{self, PlayerBlunderProfile};
+use crate::models::game::{self, AnalysisProfile, Game};
+use crate::models::game_filter::{self, Color, GameFilter, GamePage};
+use crate::models::opening_name::OpeningLevel;
+use crate::models::opening_stats::{self, DeviationStat, OpeningStat};
+use crate::models::puzzle::{self, Puzzle};
+use crate::models::training::{
+    self, NewTrainingAttempt, NewTrainingItem, Schedule, TrainingItem, TrainingKind,
+};
+use crate::models::{find_opening_by_position, get_all_openings, get_openings, Opening};
+
+#[derive(Debug)]
+pub enum DbError {
+    Config(String),
+    Migration(String),
+    Pool(PoolError),
+    Query(diesel::result::Error),
+    Task(tokio::task::JoinError),
+}
+
+impl fmt::Display for DbError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            DbError::Config(e) => write!(f, "invalid database configuration: {}", e),
+            DbError::Migration(e) => write!(f, "could not run migrations: {}", e),
+            DbError::Pool(e) => write!(f, "could not get a database connection: {}", e),
+            DbError::Query(e) => write!(f, "database query failed: {}", e),
+            DbError::Task(e) => write!(f, "database task failed: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for DbError {}
+
+impl From<diesel::result::Error> for DbError {
+    fn from(e: diesel::result::Error) -> Self {
+        DbError::Query(e)
+    }
+}
+
+/// Async handle to the connection pool. Every call checks out a connection on the
+/// blocking thread pool and returns it as soon as the query is done.
+#[derive(Clone)]
+pub struct Database {
+    pool: PgPool,
+}
+
+impl Database {
+    pub fn new(pool: PgPool) -> Self {
+        Self { pool }
+    }
+
+    pub fn pool(&self) -> &PgPool {
+        &self.pool
+    }
+
+    pub async fn run<F, T>(&self, f: F) -> Result<T, DbError>
+    where
+        F: FnOnce(&PgConnection) -> T + Send + 'static,
+        T: Send + 'static,
+    {
+        let pool = self.pool.clone();
+        match tokio::task::spawn_blocking(move || pool.get().map(|conn| f(&conn))).await {
+            Ok(Ok(res)) => Ok(res),
+            Ok(Err(e)) => Err(DbError::Pool(e)),
+            Err(e) => Err(DbError::Task(e)),
+        }
+    }
+
+    pub async fn ping(&self) -> Result<(), DbError> {
+        self.run(|conn| conn.execute("SELECT 1"))
+            .await?
+            .map(|_| ())
+            .map_err(DbError::from)
+    }
+
+    pub async fn get_game(&self, id: &str) -> Result<Option<Game>, DbError> {
+        let id = id.to_string();
+        self.run(move |conn| game::get_game(&id, conn)).await
+    }
+
+    pub async fn save_games(&self, games: Vec<Game>) -> Result<Vec<Game>, DbError> {
+        self.run(move |conn| game::save_games(games, conn))
+            .await?
+            .map_err(DbError::from)
+    }
+
+    pub async fn save_game(&self, game: Game) -> Result<Game, DbError> {
+        self.run(move |conn| game::save_game(game, conn))
+            .await?
+            .map_err(DbError::from)
+    }
+
+    pub async fn get_stale_games(
+        &self,
+        current: AnalysisProfile,
+        player: Option<String>,
+        exclude: Vec<String>,
+        limit: i64,
+    ) -> Result<Vec<Game>, DbError> {
+        self.run(move |conn| {
+            game::get_stale_games(&current, player.as_deref(), &exclude, limit, conn)
+        })
+        .await
+    }
+
+    pub async fn find_games(&self, filter: GameFilter) -> Result<GamePage, DbError> {
+        self.run(move |conn| game_filter::find_games(&filter, conn))
+            .await?
+            .map_err(DbError::from)
+    }
+
+    pub async fn get_unclassified_games(
+        &self,
+        after: String,
+        limit: i64,
+    ) -> Result<Vec<Game>, DbError> {
+        self.run(move |conn| game::get_unclassified_games(&after, limit, conn))
+            .await
+    }
+
+    pub async fn set_game_opening(&self, game: Game) -> Result<usize, DbError> {
+        self.run(move |conn| game::set_game_opening(&game, conn))
+            .await?
+            .map_err(DbError::from)
+    }
+
+    pub async fn set_game_phases(&self, game: Game) -> Result<usize, DbError> {
+        self.run(move |conn| game::set_game_phases(game, conn))
+            .await?
+            .map_err(DbError::from)
+    }
+
+    pub async fn get_opening_stats(
+        &self,
+        player: &str,
+        color: Option<Color>,
+        level: OpeningLevel,
+    ) -> Result<Vec<OpeningStat>, DbError> {
+        let player = player.to_string();
+        let stats = self
+            .run(move |conn| opening_stats::get_opening_stats(&player, color, conn))
+            .await??;
+        Ok(opening_stats::group_opening_stats(stats, level))
+    }
+
+    pub async fn replace_blunders(
+        &self,
+        game_ids: Vec<String>,
+        rows: Vec<Blunder>,
+    ) -> Result<usize, DbError> {
+        self.run(move |conn| blunder::replace_blunders(conn, &game_ids, &rows))
+            .await?
+            .map_err(DbError::from)
+    }
+
+    pub async fn get_blunder_profile(&self, player: &str) -> Result<PlayerBlunderProfile, DbError> {
+        let player = player.to_string();
+        self.run(move |conn| blunder_stats::get_blunder_profile(&player, conn))
+            .await?
+            .map_err(DbError::from)
+    }
+
+    pub async fn replace_puzzles(
+        &self,
+        player: &str,
+        game_ids: Vec<String>,
+        rows: Vec<Puzzle>,
+    ) -> Result<usize, DbError> {
+        let player = player.to_string();
+        self.run(move |conn| puzzle::replace_puzzles(conn, &player, &game_ids, &rows))
+            .await?
+            .map_err(DbError::from)
+    }
+
+    pub async fn get_puzzles(&self, player: &str) -> Result<Vec<Puzzle>, DbError> {
+        let player = player.to_string();
+        self.run(move |conn| puzzle::get_puzzles(&player, conn))
+            .await?
+            .map_err(DbError::from)
+    }
+
+    pub async fn upsert_training_items(
+        &self,
+        rows: Vec<NewTrainingItem>,
+    ) -> Result<usize, DbError> {
+        self.run(move |conn| training::upsert_training_items(&rows, conn))
+            .await?
+            .map_err(DbError::from)
+    }
+
+    pub async fn get_training_item(&self, id: i32) -> Result<Option<TrainingItem>, DbError> {
+        self.run(move |conn| training::get_training_item(id, conn))
+            .await?
+            .map_err(DbError::from)
+    }
+
+    pub async fn next_due_item(
+        &self,
+        player: &str,
+        kind: Option<TrainingKind>,
+        now: NaiveDateTime,
+    ) -> Result<Option<TrainingItem>, DbError> {
+        let player = player.to_string();
+        self.run(move |conn| training::next_due_item(&player, kind, now, conn))
+            .await?
+            .map_err(DbError::from)
+    }
+
+    pub async fn record_attempt(
+        &self,
+        attempt: NewTrainingAttempt,
+        schedule: Schedule,
+    ) -> Result<TrainingItem, DbError> {
+        self.run(move |conn| training::record_attempt(&attempt, &schedule, conn))
+            .await?
+            .map_err(DbError::from)
+    }
+
+    pub async fn get_deviation_stats(
+        &self,
+        player: &str,
+        by_player: bool,
+    ) -> Result<Vec<DeviationStat>, DbError> {
+        let player = player.to_string();
+        self.run(move |conn| opening_stats::get_deviation_stats(&player, by_player, conn))
+            .await?
+            .map_err(DbError::from)
+    }
+
+    pub async fn get_openings(&self, eco: &str) -> Result<Option<Vec<Opening>>, DbError> {
+        let eco = eco.to_string();
+        self.run(move |conn| get_openings(conn, &eco)).await
+    }
+
+    pub async fn find_opening_by_position(&self, epd: &str) -> Result<Option<Opening>, DbError> {
+        let epd = epd.to_string();
+        self.run(move |conn| find_opening_by_position(conn, &epd))
+            .await?
+            .map_err(DbError::from)
+    }
+
+    pub async fn get_all_openings(&self) -> Result<Vec<Opening>, DbError> {
+        self.run(get_all_openings).await
+    }
+}
@@ -2,18 +2,73 @@ use diesel::pg::PgConnection;
 use diesel::r2d2::{ConnectionManager, Pool, PoolError, PooledConnection};
 use diesel::Connection;
 use std::env;
+use std::str::FromStr;
+use std::time::Duration;
+
+use crate::database::DbError;
 
 pub type PgPool = Pool<ConnectionManager<PgConnection>>;
 pub type PgPooledConnection = PooledConnection<ConnectionManager<PgConnection>>;
 
-fn init_pool(database_url: &str) -> Result<PgPool, PoolError> {
+embed_migrations!("migrations");
+
+#[derive(Debug, Clone)]
+pub struct PoolConfig {
+    pub max_size: u32,
+    pub min_idle: Option<u32>,
+    pub connection_timeout: Duration,
+    pub idle_timeout: Option<Duration>,
+}
+
+fn read_env<T: FromStr>(key: &str) -> Result<Option<T>, DbError> {
+    match env::var(key) {
+        Ok(value) => match value.parse::<T>() {
+            Ok(parsed) => Ok(Some(parsed)),
+            Err(_) => Err(DbError::Config(format!(
+                "{} has an invalid value: {}",
+                key, value
+            ))),
+        },
+        Err(_) => Ok(None),
+    }
+}
+
+impl PoolConfig {
+    pub fn from_env() -> Result<Self, DbError> {
+        Ok(Self {
+            max_size: read_env("DATABASE_POOL_MAX_SIZE")?.unwrap_or(10),
+            min_idle: read_env("DATABASE_POOL_MIN_IDLE")?,
+            connection_timeout: Duration::from_secs(
+                read_env("DATABASE_POOL_TIMEOUT_SECS")?.unwrap_or(5),
+            ),
+            idle_timeout: read_env("DATABASE_POOL_IDLE_TIMEOUT_SECS")?.map(Duration::from_secs),
+        })
+    }
+}
+
+fn database_url() -> Result<String, DbError> {
+    env::var("DATABASE_URL").map_err(|_| DbError::Config("DATABASE_URL must be set".to_string()))
+}
+
+fn init_pool(database_url: &str, config: &PoolConfig) -> Result<PgPool, PoolError> {
     let manager = ConnectionManager::<PgConnection>::new(database_url);
-    Pool::builder().build(manager)
+    Pool::builder()
+        .max_size(config.max_size)
+        .min_idle(config.min_idle)
+        .connection_timeout(config.connection_timeout)
+        .idle_timeout(config.idle_timeout)
+        .build(manager)
 }
 
-pub fn establish_connection() -> PgPool {
-    let database_url = env::var("DATABASE_URL").expect("DATABASE_URL must be set");
-    init_pool(&database_url).expect("Failed to create pool")
+pub fn establish_connection() -> Result<PgPool, DbError> {
+    let database_url = database_url()?;
+    let config = PoolConfig::from_env()?;
+    init_pool(&database_url, &config).map_err(DbError::Pool)
+}
+
+pub fn run_migrations(pool: &PgPool) -> Result<(), DbError> {
+    let conn = pool.get().map_err(DbError::Pool)?;
+    embedded_migrations::run(&conn).map_err(|e| DbError::Migration(e.to_string()))
 }
 
 pub fn pg_pool_handler(pool: &PgPool) -> Result<PgPooledConnection, PoolError> {
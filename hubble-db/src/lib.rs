@@ -1,8 +1,12 @@
 #[macro_use]
 pub extern crate diesel;
+#[macro_use]
+extern crate diesel_migrations;
 
+mod database;
 mod db;
 pub mod models;
 mod schema;
+pub use database::{Database, DbError};
 pub use db::*;
 pub use diesel::pg::PgConnection;
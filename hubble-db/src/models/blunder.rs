@@ -0,0 +1,34 @@
+use serde::{Deserialize, Serialize};
+
+use diesel::pg::PgConnection;
+use diesel::prelude::*;
+
+use crate::schema::blunders;
+
+#[derive(Insertable, Queryable, Deserialize, Serialize, Clone, Debug, PartialEq, Eq)]
+#[table_name = "blunders"]
+pub struct Blunder {
+    pub game_id: String,
+    pub ply: i32, //Same index as in the game's blunders, even plies are white's moves
+    pub uci: String,
+    pub san: String,
+    pub piece: String, //Role of the moved piece, "pawn", "knight", ...
+    pub motifs: Vec<String>,
+}
+
+/// Replaces the stored blunders of the given games, games without blunders are cleared.
+pub fn replace_blunders(
+    conn: &PgConnection,
+    game_ids: &[String],
+    rows: &[Blunder],
+) -> Result<usize, diesel::result::Error> {
+    conn.transaction(|| {
+        diesel::delete(blunders::table.filter(blunders::game_id.eq_any(game_ids))).execute(conn)?;
+        if rows.is_empty() {
+            return Ok(0);
+        }
+        diesel::insert_into(blunders::table)
+            .values(rows)
+            .execute(conn)
+    })
+}
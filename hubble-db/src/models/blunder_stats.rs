@@ -0,0 +1,151 @@
+use serde::Serialize;
+
+use diesel::pg::PgConnection;
+use diesel::prelude::*;
+use diesel::sql_query;
+use diesel::sql_types::{BigInt, Double, Text, Varchar};
+
+// Centipawns before the move, from the player's side, above which a position counts as winning
+pub const WINNING_MARGIN: i32 = 150;
+
+// Every move of the player in their stored games, with its phase, the eval before it
+// and whether it was a blunder. Scores are from the side to move after each ply,
+// so the score after the previous ply is already from the player's point of view.
+const PLAYER_MOVES: &str = "\
+    WITH player_games AS ( \
+        SELECT g.id, g.played_at, g.scores, g.blunders, \
+        CASE WHEN lower(g.white) = lower($1) THEN 0 ELSE 1 END AS side, \
+        jsonb_array_length(g.moves->'data') AS n, \
+        COALESCE(g.middle_game, jsonb_array_length(g.moves->'data')) AS m, \
+        COALESCE(g.end_game, jsonb_array_length(g.moves->'data')) AS e \
+        FROM games g WHERE lower(g.white) = lower($1) OR lower(g.black) = lower($1) \
+    ), player_moves AS ( \
+        SELECT pg.id, pg.played_at, pg.side, s.ply, \
+        CASE WHEN s.ply < pg.m THEN 'opening' WHEN s.ply < pg.e THEN 'middle_game' ELSE 'end_game' END AS phase, \
+        CASE WHEN s.ply = 0 THEN 0 ELSE (pg.scores->'data'->>(s.ply - 1))::int END AS eval_before, \
+        COALESCE(((pg.blunders->'opening') || (pg.blunders->'middle_game') || (pg.blunders->'end_game')) \
+            @> to_jsonb(s.ply), false) AS is_blunder, \
+        b.piece, b.motifs \
+        FROM player_games pg \
+        CROSS JOIN LATERAL generate_series(pg.side, pg.n - 1, 2) AS s(ply) \
+        LEFT JOIN blunders b ON b.game_id = pg.id AND b.ply = s.ply \
+    ) ";
+
+#[derive(QueryableByName, Serialize, Debug, Clone)]
+pub struct BlunderRate {
+    #[sql_type = "Varchar"]
+    pub key: String,
+    #[sql_type = "BigInt"]
+    pub moves: i64, //Moves of the player in the group, all of them when grouped by piece or motif
+    #[sql_type = "BigInt"]
+    pub blunders: i64,
+    #[sql_type = "Double"]
+    pub per_100_moves: f64,
+}
+
+#[derive(Serialize, Debug)]
+pub struct PlayerBlunderProfile {
+    pub player: String,
+    pub moves: i64,
+    pub blunders: i64,
+    pub per_100_moves: f64,
+    pub by_phase: Vec<BlunderRate>,
+    pub by_color: Vec<BlunderRate>,
+    pub by_piece: Vec<BlunderRate>, //Unknown for games whose blunders were never indexed
+    pub by_motif: Vec<BlunderRate>, //A blunder can have several motifs or none
+    pub by_situation: Vec<BlunderRate>, //Winning, equal or losing before the move
+    pub by_month: Vec<BlunderRate>,
+}
+
+fn blunder_rates(
+    player: &str,
+    key: &str,
+    order: &str,
+    conn: &PgConnection,
+) -> QueryResult<Vec<BlunderRate>> {
+    sql_query(format!(
+        "{} SELECT {} AS key, COUNT(*) AS moves, \
+         COUNT(*) FILTER (WHERE is_blunder) AS blunders, \
+         (100.0 * COUNT(*) FILTER (WHERE is_blunder) / COUNT(*))::float8 AS per_100_moves \
+         FROM player_moves GROUP BY 1 ORDER BY {}",
+        PLAYER_MOVES, key, order
+    ))
+    .bind::<Text, _>(player)
+    .load::<BlunderRate>(conn)
+}
+
+fn blunder_shares(
+    player: &str,
+    key: &str,
+    from: &str,
+    conn: &PgConnection,
+) -> QueryResult<Vec<BlunderRate>> {
+    //Pieces and motifs are only known for blunders, so rates are against every move
+    sql_query(format!(
+        "{} SELECT {} AS key, \
+         (SELECT COUNT(*) FROM player_moves) AS moves, \
+         COUNT(*) AS blunders, \
+         (100.0 * COUNT(*) / (SELECT COUNT(*) FROM player_moves))::float8 AS per_100_moves \
+         FROM {} WHERE is_blunder GROUP BY 1 ORDER BY blunders DESC",
+        PLAYER_MOVES, key, from
+    ))
+    .bind::<Text, _>(player)
+    .load::<BlunderRate>(conn)
+}
+
+pub fn get_blunder_profile(player: &str, conn: &PgConnection) -> QueryResult<PlayerBlunderProfile> {
+    let situation = format!(
+        "CASE WHEN eval_before IS NULL THEN 'unknown' \
+         WHEN eval_before > {margin} THEN 'winning' \
+         WHEN eval_before < -{margin} THEN 'losing' \
+         ELSE 'equal' END",
+        margin = WINNING_MARGIN
+    );
+
+    let by_phase = blunder_rates(
+        player,
+        "phase",
+        "CASE phase WHEN 'opening' THEN 0 WHEN 'middle_game' THEN 1 ELSE 2 END",
+        conn,
+    )?;
+    let by_color = blunder_rates(
+        player,
+        "CASE side WHEN 0 THEN 'white' ELSE 'black' END",
+        "1",
+        conn,
+    )?;
+    let by_situation = blunder_rates(player, &situation, "1", conn)?;
+    let by_month = blunder_rates(
+        player,
+        "COALESCE(to_char(played_at, 'YYYY-MM'), 'unknown')",
+        "1",
+        conn,
+    )?;
+    let by_piece = blunder_shares(player, "COALESCE(piece, 'unknown')", "player_moves", conn)?;
+    let by_motif = blunder_shares(
+        player,
+        "motif",
+        "player_moves CROSS JOIN LATERAL unnest(motifs) AS m(motif)",
+        conn,
+    )?;
+
+    let moves = by_color.iter().map(|rate| rate.moves).sum::<i64>();
+    let blunders = by_color.iter().map(|rate| rate.blunders).sum::<i64>();
+
+    Ok(PlayerBlunderProfile {
+        player: player.to_string(),
+        moves,
+        blunders,
+        per_100_moves: if moves > 0 {
+            100. * blunders as f64 / moves as f64
+        } else {
+            0.
+        },
+        by_phase,
+        by_color,
+        by_piece,
+        by_motif,
+        by_situation,
+        by_month,
+    })
+}
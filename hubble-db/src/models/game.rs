@@ -1,8 +1,14 @@
+use chrono::{NaiveDateTime, Utc};
 use serde::{Deserialize, Serialize};
 use serde_json::json;
+use std::collections::BTreeMap;
 
+use diesel::dsl::sql;
+use diesel::expression::SqlLiteral;
+use diesel::pg::upsert::excluded;
 use diesel::pg::PgConnection;
 use diesel::prelude::*;
+use diesel::sql_types::{Integer, Jsonb, Nullable, Timestamp, Varchar};
 use diesel::Queryable;
 
 use crate::schema::games;
@@ -24,6 +30,17 @@ pub struct GameRaw {
     middle_game: Option<i32>,
     end_game: Option<i32>,
     blunders: serde_json::Value,
+    analysis_version: i32,
+    engine: Option<String>,
+    engine_nodes: Option<i32>,
+    analysed_at: Option<NaiveDateTime>,
+    played_at: Option<NaiveDateTime>,
+    time_control: Option<String>,
+    speed: Option<String>,
+    book_opening_id: Option<i32>,
+    left_book_ply: Option<i32>,
+    left_book_by: Option<String>,
+    book_exit_swing: Option<i32>,
 }
 
 impl GameRaw {
@@ -54,15 +71,70 @@ impl GameRaw {
             end_game: self.end_game,
             middle_game: self.middle_game,
             blunders: serde_json::from_value(self.blunders).expect("Could not parse blunders from database"),
+            analysis: AnalysisProfile {
+                engine: self.engine,
+                engine_nodes: self.engine_nodes,
+                version: self.analysis_version,
+                analysed_at: self.analysed_at,
+            },
+            played_at: self.played_at,
+            time_control: self.time_control,
+            speed: self.speed,
+            book_opening_id: self.book_opening_id,
+            left_book_ply: self.left_book_ply,
+            left_book_by: self.left_book_by,
+            book_exit_swing: self.book_exit_swing,
+        }
+    }
+}
+
+#[derive(Deserialize, Serialize, Clone, Debug, PartialEq)]
+pub struct AnalysisProfile {
+    pub engine: Option<String>,
+    pub engine_nodes: Option<i32>,
+    pub version: i32, //Version of the blunder and phase classification
+    pub analysed_at: Option<NaiveDateTime>,
+}
+
+impl AnalysisProfile {
+    pub fn empty() -> Self {
+        Self {
+            engine: None,
+            engine_nodes: None,
+            version: 0,
+            analysed_at: None,
+        }
+    }
+
+    pub fn new(engine: &str, engine_nodes: i32, version: i32) -> Self {
+        Self {
+            engine: Some(engine.to_string()),
+            engine_nodes: Some(engine_nodes),
+            version,
+            analysed_at: Some(Utc::now().naive_utc()),
         }
     }
+
+    pub fn is_stale(&self, current: &AnalysisProfile) -> bool {
+        //Same ordering as the upsert in save_games, so a re-analysed game is never stale again
+        let nodes = self.engine_nodes.unwrap_or(0);
+        let current_nodes = current.engine_nodes.unwrap_or(0);
+
+        self.version < current.version
+            || self.version == current.version && nodes < current_nodes
+            || self.version == current.version
+                && nodes == current_nodes
+                && self.engine != current.engine
+    }
 }
 
 #[derive(Deserialize, Serialize, Clone, Debug)]
 pub struct Blunders {
     pub opening: Vec<i32>,
     pub middle_game: Vec<i32>,
-    pub end_game: Vec<i32>
+    pub end_game: Vec<i32>,
+    #[serde(default)]
+    pub motifs: BTreeMap<i32, Vec<String>>, //Tactics behind the blunder at each ply, games analysed before motifs have none
 }
 
 impl Blunders {
@@ -71,6 +143,7 @@ impl Blunders {
             opening: Vec::new(),
             middle_game: Vec::new(),
             end_game: Vec::new(),
+            motifs: BTreeMap::new(),
         }
     }
 }
@@ -89,7 +162,15 @@ pub struct Game {
     pub winner: Option<String>,
     pub middle_game: Option<i32>,
     pub end_game: Option<i32>,
-    pub blunders:  Blunders
+    pub blunders:  Blunders,
+    pub analysis: AnalysisProfile,
+    pub played_at: Option<NaiveDateTime>,
+    pub time_control: Option<String>,
+    pub speed: Option<String>,
+    pub book_opening_id: Option<i32>, //Deepest position from the openings table reached in the game
+    pub left_book_ply: Option<i32>,   //Index of the first move played out of book
+    pub left_book_by: Option<String>, //"white" or "black", None if the game never left book
+    pub book_exit_swing: Option<i32>, //Eval change in the moves after leaving book, for the side that left
 }
 
 impl Game {
@@ -106,7 +187,15 @@ impl Game {
             winner: None,
             middle_game: None,
             end_game: None,
-            blunders: Blunders::empty()
+            blunders: Blunders::empty(),
+            analysis: AnalysisProfile::empty(),
+            played_at: None,
+            time_control: None,
+            speed: None,
+            book_opening_id: None,
+            left_book_ply: None,
+            left_book_by: None,
+            book_exit_swing: None,
         }
     }
 
@@ -130,6 +219,17 @@ impl Game {
             end_game: self.end_game,
             middle_game: self.middle_game,
             blunders: serde_json::to_value(self.blunders).expect("Could not deserilize blunders to json"),
+            analysis_version: self.analysis.version,
+            engine: self.analysis.engine,
+            engine_nodes: self.analysis.engine_nodes,
+            analysed_at: self.analysis.analysed_at,
+            played_at: self.played_at,
+            time_control: self.time_control,
+            speed: self.speed,
+            book_opening_id: self.book_opening_id,
+            left_book_ply: self.left_book_ply,
+            left_book_by: self.left_book_by,
+            book_exit_swing: self.book_exit_swing,
         }
     }
 }
@@ -137,8 +237,8 @@ impl Game {
 #[allow(dead_code)]
 pub fn get_games_player(user_id: &str, conn: &PgConnection) -> Vec<Game> {
     let raws = games::table
-        .filter(games::white.eq(user_id))
-        .or_filter(games::black.eq(user_id))
+        .filter(lower(games::white).eq(lower(user_id)))
+        .or_filter(lower(games::black).eq(lower(user_id)))
         .load::<GameRaw>(conn)
         .expect("ERROR LOADING");
     let mut games = Vec::new();
@@ -162,16 +262,59 @@ pub fn get_games(conn: &PgConnection) -> Vec<Game> {
     games
 }
 
+sql_function!(fn coalesce(x: Nullable<Integer>, y: Integer) -> Integer);
+sql_function!(fn lower(x: Varchar) -> Varchar);
+
+fn keep_newest_analysis<ST>(column: &str) -> SqlLiteral<ST> {
+    //Only replace the analysis if the incoming one is at least as new and as deep as the stored one
+    sql::<ST>(&format!(
+        "CASE WHEN (excluded.analysis_version, COALESCE(excluded.engine_nodes, 0)) \
+         >= (games.analysis_version, COALESCE(games.engine_nodes, 0)) \
+         THEN excluded.{column} ELSE games.{column} END"
+    ))
+}
+
 pub fn save_games(
     games: Vec<Game>,
     conn: &PgConnection,
 ) -> Result<Vec<Game>, diesel::result::Error> {
+    if games.is_empty() {
+        return Ok(Vec::new());
+    }
+
     let raw_games = games
         .into_iter()
         .map(|x| x.into_raw())
         .collect::<Vec<GameRaw>>();
+
     match diesel::insert_into(games::table)
         .values(&raw_games)
+        .on_conflict(games::id)
+        .do_update()
+        .set((
+            games::opening_id.eq(excluded(games::opening_id)),
+            games::moves.eq(excluded(games::moves)),
+            games::white.eq(excluded(games::white)),
+            games::black.eq(excluded(games::black)),
+            games::white_rating.eq(excluded(games::white_rating)),
+            games::black_rating.eq(excluded(games::black_rating)),
+            games::winner.eq(excluded(games::winner)),
+            games::played_at.eq(excluded(games::played_at)),
+            games::time_control.eq(excluded(games::time_control)),
+            games::speed.eq(excluded(games::speed)),
+            games::book_opening_id.eq(excluded(games::book_opening_id)),
+            games::left_book_ply.eq(excluded(games::left_book_ply)),
+            games::left_book_by.eq(excluded(games::left_book_by)),
+            games::book_exit_swing.eq(excluded(games::book_exit_swing)),
+            games::scores.eq(keep_newest_analysis::<Jsonb>("scores")),
+            games::blunders.eq(keep_newest_analysis::<Jsonb>("blunders")),
+            games::middle_game.eq(keep_newest_analysis::<Nullable<Integer>>("middle_game")),
+            games::end_game.eq(keep_newest_analysis::<Nullable<Integer>>("end_game")),
+            games::analysis_version.eq(keep_newest_analysis::<Integer>("analysis_version")),
+            games::engine.eq(keep_newest_analysis::<Nullable<Varchar>>("engine")),
+            games::engine_nodes.eq(keep_newest_analysis::<Nullable<Integer>>("engine_nodes")),
+            games::analysed_at.eq(keep_newest_analysis::<Nullable<Timestamp>>("analysed_at")),
+        ))
         .get_results(conn)
     {
         Ok(returning) => Ok(returning
@@ -183,17 +326,92 @@ pub fn save_games(
 }
 
 pub fn save_game(game: Game, conn: &PgConnection) -> Result<Game, diesel::result::Error> {
-    let raw = game.into_raw();
-
-    match diesel::insert_into(games::table)
-        .values(raw)
-        .get_result::<GameRaw>(conn)
-    {
-        Ok(ret) => Ok(ret.to_game()),
+    match save_games(vec![game], conn) {
+        Ok(mut saved) => Ok(saved.remove(0)),
         Err(e) => Err(e),
     }
 }
 
+pub fn get_stale_games(
+    current: &AnalysisProfile,
+    player: Option<&str>,
+    exclude: &[String], //Games that could not be re-analysed earlier in the run
+    limit: i64,
+    conn: &PgConnection,
+) -> Vec<Game> {
+    let current_nodes = current.engine_nodes.unwrap_or(0);
+    let mut query = games::table
+        .filter(
+            games::analysis_version.lt(current.version).or(games::analysis_version
+                .eq(current.version)
+                .and(
+                    coalesce(games::engine_nodes, 0).lt(current_nodes).or(coalesce(
+                        games::engine_nodes,
+                        0,
+                    )
+                    .eq(current_nodes)
+                    .and(games::engine.is_distinct_from(current.engine.clone()))),
+                )),
+        )
+        .into_boxed();
+
+    if let Some(user_id) = player {
+        query = query.filter(
+            lower(games::white)
+                .eq(lower(user_id))
+                .or(lower(games::black).eq(lower(user_id))),
+        );
+    }
+    if !exclude.is_empty() {
+        query = query.filter(games::id.ne_all(exclude));
+    }
+
+    let raws = query
+        .order(games::analysed_at.asc().nulls_first())
+        .limit(limit)
+        .load::<GameRaw>(conn)
+        .expect("ERROR LOADING");
+
+    raws.into_iter().map(|x| x.to_game()).collect()
+}
+
+/// Games without a book exit, in id order after `after`. Games that never left book
+/// have none either, paging by id visits them once per backfill.
+pub fn get_unclassified_games(after: &str, limit: i64, conn: &PgConnection) -> Vec<Game> {
+    let raws = games::table
+        .filter(games::left_book_by.is_null())
+        .filter(games::id.gt(after))
+        .order(games::id)
+        .limit(limit)
+        .load::<GameRaw>(conn)
+        .expect("ERROR LOADING");
+
+    raws.into_iter().map(|x| x.to_game()).collect()
+}
+
+pub fn set_game_opening(game: &Game, conn: &PgConnection) -> Result<usize, diesel::result::Error> {
+    diesel::update(games::table.find(&game.id))
+        .set((
+            games::book_opening_id.eq(game.book_opening_id),
+            games::left_book_ply.eq(game.left_book_ply),
+            games::left_book_by.eq(&game.left_book_by),
+            games::book_exit_swing.eq(game.book_exit_swing),
+        ))
+        .execute(conn)
+}
+
+/// Stores recomputed phases and the blunders regrouped by them, leaving the analysis as it is.
+pub fn set_game_phases(game: Game, conn: &PgConnection) -> Result<usize, diesel::result::Error> {
+    let raw = game.into_raw();
+    diesel::update(games::table.find(&raw.id))
+        .set((
+            games::middle_game.eq(raw.middle_game),
+            games::end_game.eq(raw.end_game),
+            games::blunders.eq(&raw.blunders),
+        ))
+        .execute(conn)
+}
+
 pub fn get_game(id: &str, conn: &PgConnection) -> Option<Game> {
     match games::table.filter(games::id.eq(id)).first::<GameRaw>(conn) {
         Ok(ret) => Some(ret.to_game()),
@@ -0,0 +1,254 @@
+use chrono::NaiveDateTime;
+use serde::{Deserialize, Serialize};
+use std::str::FromStr;
+
+use diesel::pg::{Pg, PgConnection};
+use diesel::prelude::*;
+use diesel::sql_types::{Nullable, Varchar};
+
+use crate::models::game::{Game, GameRaw};
+use crate::schema::games;
+
+#[derive(Deserialize, Serialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Color {
+    White,
+    Black,
+}
+
+#[derive(Deserialize, Serialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum GameOutcome {
+    //Seen from the filtered player, or from white when no player is given
+    Win,
+    Draw,
+    Loss,
+}
+
+#[derive(Deserialize, Serialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum SortField {
+    PlayedAt,
+    WhiteRating,
+    BlackRating,
+    Id,
+}
+
+impl FromStr for Color {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "white" => Ok(Color::White),
+            "black" => Ok(Color::Black),
+            _ => Err(()),
+        }
+    }
+}
+
+impl FromStr for GameOutcome {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "win" => Ok(GameOutcome::Win),
+            "draw" => Ok(GameOutcome::Draw),
+            "loss" => Ok(GameOutcome::Loss),
+            _ => Err(()),
+        }
+    }
+}
+
+impl FromStr for SortField {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "played_at" => Ok(SortField::PlayedAt),
+            "white_rating" => Ok(SortField::WhiteRating),
+            "black_rating" => Ok(SortField::BlackRating),
+            "id" => Ok(SortField::Id),
+            _ => Err(()),
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct GameFilter {
+    pub player: Option<String>,
+    pub exclude_player: Option<String>, //Games of this player are left out
+    pub color: Option<Color>,
+    pub result: Option<GameOutcome>,
+    pub eco: Option<String>, //Prefix, "B" matches every B opening
+    pub min_rating: Option<i32>,
+    pub max_rating: Option<i32>,
+    pub from: Option<NaiveDateTime>,
+    pub to: Option<NaiveDateTime>,
+    pub time_control: Option<String>, //Either a speed ("blitz") or an exact time control ("300+3")
+    pub sort: SortField,
+    pub descending: bool,
+    pub limit: i64,
+    pub offset: i64,
+}
+
+impl Default for GameFilter {
+    fn default() -> Self {
+        Self {
+            player: None,
+            exclude_player: None,
+            color: None,
+            result: None,
+            eco: None,
+            min_rating: None,
+            max_rating: None,
+            from: None,
+            to: None,
+            time_control: None,
+            sort: SortField::PlayedAt,
+            descending: true,
+            limit: 50,
+            offset: 0,
+        }
+    }
+}
+
+#[derive(Serialize, Debug)]
+pub struct GamePage {
+    pub games: Vec<Game>,
+    pub total: i64,
+    pub limit: i64,
+    pub offset: i64,
+}
+
+sql_function!(fn lower(x: Varchar) -> Varchar);
+sql_function! {
+    #[sql_name = "lower"]
+    fn lower_nullable(x: Nullable<Varchar>) -> Nullable<Varchar>;
+}
+
+fn filtered_query(filter: &GameFilter) -> games::BoxedQuery<'_, Pg> {
+    //Player names are matched ignoring case, like lichess usernames
+    let mut query = games::table.into_boxed();
+    let min_rating = filter.min_rating.unwrap_or(i32::MIN);
+    let max_rating = filter.max_rating.unwrap_or(i32::MAX);
+    let rating_filter = filter.min_rating.is_some() || filter.max_rating.is_some();
+
+    if let Some(player) = &filter.player {
+        query = match filter.color {
+            Some(Color::White) => query.filter(lower(games::white).eq(lower(player))),
+            Some(Color::Black) => query.filter(lower(games::black).eq(lower(player))),
+            None => query.filter(
+                lower(games::white)
+                    .eq(lower(player))
+                    .or(lower(games::black).eq(lower(player))),
+            ),
+        };
+
+        query = match filter.result {
+            Some(GameOutcome::Win) => {
+                query.filter(lower_nullable(games::winner).eq(lower(player).nullable()))
+            }
+            Some(GameOutcome::Draw) => query.filter(games::winner.is_null()),
+            Some(GameOutcome::Loss) => {
+                query.filter(lower_nullable(games::winner).ne(lower(player).nullable()))
+            }
+            None => query,
+        };
+
+        if rating_filter {
+            let white_in_range = lower(games::white)
+                .eq(lower(player))
+                .and(games::white_rating.between(min_rating, max_rating));
+            let black_in_range = lower(games::black)
+                .eq(lower(player))
+                .and(games::black_rating.between(min_rating, max_rating));
+
+            query = match filter.color {
+                Some(Color::White) => query.filter(white_in_range),
+                Some(Color::Black) => query.filter(black_in_range),
+                None => query.filter(white_in_range.or(black_in_range)),
+            };
+        }
+    } else {
+        query = match filter.result {
+            Some(GameOutcome::Win) => query.filter(games::winner.eq(games::white.nullable())),
+            Some(GameOutcome::Draw) => query.filter(games::winner.is_null()),
+            Some(GameOutcome::Loss) => query.filter(games::winner.eq(games::black.nullable())),
+            None => query,
+        };
+
+        if rating_filter {
+            query = query
+                .filter(games::white_rating.between(min_rating, max_rating))
+                .filter(games::black_rating.between(min_rating, max_rating));
+        }
+    }
+
+    if let Some(excluded) = &filter.exclude_player {
+        query = query.filter(
+            lower(games::white)
+                .ne(lower(excluded))
+                .and(lower(games::black).ne(lower(excluded))),
+        );
+    }
+
+    if let Some(eco) = &filter.eco {
+        //Only the trailing % is a wildcard, postgres escapes with a backslash by default
+        let prefix = eco
+            .replace('\\', "\\\\")
+            .replace('%', "\\%")
+            .replace('_', "\\_");
+        query = query.filter(games::opening_id.like(format!("{}%", prefix)));
+    }
+
+    if let Some(from) = filter.from {
+        query = query.filter(games::played_at.ge(from));
+    }
+
+    if let Some(to) = filter.to {
+        query = query.filter(games::played_at.lt(to));
+    }
+
+    if let Some(time_control) = &filter.time_control {
+        query = query.filter(
+            games::speed
+                .eq(time_control)
+                .or(games::time_control.eq(time_control)),
+        );
+    }
+
+    query
+}
+
+fn sorted_query(filter: &GameFilter) -> games::BoxedQuery<'_, Pg> {
+    let query = filtered_query(filter);
+
+    let query = match (filter.sort, filter.descending) {
+        (SortField::PlayedAt, true) => query.order(games::played_at.desc().nulls_last()),
+        (SortField::PlayedAt, false) => query.order(games::played_at.asc().nulls_last()),
+        (SortField::WhiteRating, true) => query.order(games::white_rating.desc().nulls_last()),
+        (SortField::WhiteRating, false) => query.order(games::white_rating.asc().nulls_last()),
+        (SortField::BlackRating, true) => query.order(games::black_rating.desc().nulls_last()),
+        (SortField::BlackRating, false) => query.order(games::black_rating.asc().nulls_last()),
+        (SortField::Id, true) => query.order(games::id.desc()),
+        (SortField::Id, false) => query.order(games::id.asc()),
+    };
+
+    //Ties are broken on id so pages are stable
+    query.then_order_by(games::id.asc())
+}
+
+pub fn find_games(filter: &GameFilter, conn: &PgConnection) -> QueryResult<GamePage> {
+    let total = filtered_query(filter).count().get_result::<i64>(conn)?;
+    let raws = sorted_query(filter)
+        .limit(filter.limit)
+        .offset(filter.offset)
+        .load::<GameRaw>(conn)?;
+
+    Ok(GamePage {
+        games: raws.into_iter().map(|x| x.to_game()).collect(),
+        total,
+        limit: filter.limit,
+        offset: filter.offset,
+    })
+}
@@ -1,4 +1,19 @@
+pub mod blunder;
+pub mod blunder_stats;
 pub mod game;
+pub mod game_filter;
 mod opening;
+pub mod opening_name;
+pub mod opening_stats;
+pub mod puzzle;
+pub mod training;
 
-pub use opening::{get_all_openings, get_openings, insert_opening, insert_openings, Opening};
+/// Name puzzles and training items are stored under, lichess usernames ignore case.
+pub fn player_key(player: &str) -> String {
+    player.to_lowercase()
+}
+
+pub use opening::{
+    find_opening_by_position, get_all_openings, get_openings, insert_opening, insert_openings,
+    upsert_openings, NewOpening, Opening,
+};
@@ -2,25 +2,65 @@ use serde::{Deserialize, Serialize};
 
 use diesel::pg::PgConnection;
 use diesel::prelude::*;
+use diesel::pg::upsert::excluded;
 use diesel::Queryable;
 
 use crate::schema::openings;
 
-#[derive(Insertable, Queryable, Deserialize, Serialize, Debug)]
+#[derive(Insertable, Queryable, Deserialize, Serialize, Clone, Debug)]
 #[table_name = "openings"]
 pub struct Opening {
     pub id: i32, //Means id needs to be set for insert. Not ideal but fine since insert is only done ones.
     pub eco: String,
     pub name: String,
     pub pgn: String,
+    pub uci: Option<String>, //Space separated, filled in by parse_openings
+    pub epd: Option<String>, //Position after the last move
+    pub ply: Option<i32>,
 }
 
 impl Opening {
     pub fn new(id: i32, eco: String, name: String, pgn: String) -> Self {
-        Self { id, eco, name, pgn }
+        Self {
+            id,
+            eco,
+            name,
+            pgn,
+            uci: None,
+            epd: None,
+            ply: None,
+        }
     }
 }
 
+#[derive(Insertable, Debug)]
+#[table_name = "openings"]
+pub struct NewOpening {
+    pub eco: String,
+    pub name: String,
+    pub pgn: String,
+    pub uci: String,
+    pub epd: String,
+    pub ply: i32,
+}
+
+pub fn upsert_openings(
+    conn: &PgConnection,
+    openings: &[NewOpening],
+) -> Result<usize, diesel::result::Error> {
+    //Lines already in the table only get their precomputed position updated
+    diesel::insert_into(openings::table)
+        .values(openings)
+        .on_conflict((openings::eco, openings::name, openings::pgn))
+        .do_update()
+        .set((
+            openings::uci.eq(excluded(openings::uci)),
+            openings::epd.eq(excluded(openings::epd)),
+            openings::ply.eq(excluded(openings::ply)),
+        ))
+        .execute(conn)
+}
+
 pub fn insert_opening(
     conn: &PgConnection,
     opening: Opening,
@@ -50,6 +90,18 @@ pub fn get_openings(conn: &PgConnection, eco: &str) -> Option<Vec<Opening>> {
     }
 }
 
+pub fn find_opening_by_position(
+    conn: &PgConnection,
+    epd: &str,
+) -> Result<Option<Opening>, diesel::result::Error> {
+    //Several lines can reach the same position, the shortest one names it
+    openings::table
+        .filter(openings::epd.eq(epd))
+        .order((openings::ply.asc(), openings::id.asc()))
+        .first::<Opening>(conn)
+        .optional()
+}
+
 pub fn get_all_openings(conn: &PgConnection) -> Vec<Opening> {
     match openings::table.load::<Opening>(conn) {
         Ok(ret) => ret,
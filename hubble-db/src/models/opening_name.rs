@@ -0,0 +1,74 @@
+use serde::{Deserialize, Serialize};
+use std::str::FromStr;
+
+#[derive(Deserialize, Serialize, Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+#[serde(rename_all = "snake_case")]
+pub enum OpeningLevel {
+    Family,       //Sicilian Defense
+    Variation,    //Sicilian Defense: Najdorf Variation
+    SubVariation, //Sicilian Defense: Najdorf Variation, English Attack
+}
+
+impl FromStr for OpeningLevel {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "family" => Ok(OpeningLevel::Family),
+            "variation" => Ok(OpeningLevel::Variation),
+            "sub_variation" | "sub-variation" => Ok(OpeningLevel::SubVariation),
+            _ => Err(()),
+        }
+    }
+}
+
+/// An opening name split the way the chess-openings files write them,
+/// "Family: Variation, Sub-variation, ...".
+#[derive(Serialize, Clone, Debug, PartialEq, Eq)]
+pub struct OpeningName {
+    pub family: String,
+    pub variation: Option<String>,
+    pub sub_variation: Option<String>, //Everything after the variation, deeper lines included
+}
+
+impl OpeningName {
+    pub fn parse(name: &str) -> Self {
+        let (family, rest) = match name.split_once(':') {
+            Some((family, rest)) => (family, Some(rest)),
+            None => (name, None),
+        };
+        let (variation, sub_variation) = match rest.map(|rest| rest.split_once(',')) {
+            Some(Some((variation, sub_variation))) => (Some(variation), Some(sub_variation)),
+            Some(None) => (rest, None),
+            None => (None, None),
+        };
+        let part = |part: Option<&str>| {
+            part.map(str::trim)
+                .filter(|part| !part.is_empty())
+                .map(str::to_string)
+        };
+
+        Self {
+            family: family.trim().to_string(),
+            variation: part(variation),
+            sub_variation: part(sub_variation),
+        }
+    }
+
+    /// The name cut down to the given level. Names without that level keep the deepest one they have.
+    pub fn at(&self, level: OpeningLevel) -> String {
+        let mut name = self.family.clone();
+        if level == OpeningLevel::Family {
+            return name;
+        }
+        if let Some(variation) = &self.variation {
+            name = format!("{}: {}", name, variation);
+            if level == OpeningLevel::SubVariation {
+                if let Some(sub_variation) = &self.sub_variation {
+                    name = format!("{}, {}", name, sub_variation);
+                }
+            }
+        }
+        name
+    }
+}
@@ -0,0 +1,166 @@
+use serde::Serialize;
+
+use diesel::pg::PgConnection;
+use diesel::prelude::*;
+use diesel::sql_query;
+use diesel::sql_types::{BigInt, Double, Integer, Nullable, Text, Varchar};
+
+use crate::models::game_filter::Color;
+use crate::models::opening_name::{OpeningLevel, OpeningName};
+use std::cmp::Reverse;
+use std::collections::HashMap;
+
+#[derive(QueryableByName, Serialize, Debug)]
+pub struct OpeningStat {
+    #[sql_type = "Nullable<Integer>"]
+    pub opening_id: Option<i32>, //None for games that never reached a known position
+    #[sql_type = "Nullable<Varchar>"]
+    pub eco: Option<String>,
+    #[sql_type = "Nullable<Varchar>"]
+    pub name: Option<String>,
+    #[sql_type = "BigInt"]
+    pub games: i64,
+    #[sql_type = "BigInt"]
+    pub won: i64,
+    #[sql_type = "BigInt"]
+    pub drawn: i64,
+    #[sql_type = "BigInt"]
+    pub lost: i64,
+}
+
+fn color_param(color: Option<Color>) -> &'static str {
+    match color {
+        Some(Color::White) => "white",
+        Some(Color::Black) => "black",
+        None => "both",
+    }
+}
+
+pub fn get_opening_stats(
+    player: &str,
+    color: Option<Color>,
+    conn: &PgConnection,
+) -> QueryResult<Vec<OpeningStat>> {
+    sql_query(
+        "SELECT o.id AS opening_id, o.eco, o.name, \
+         COUNT(*) AS games, \
+         COUNT(*) FILTER (WHERE lower(g.winner) = lower($1)) AS won, \
+         COUNT(*) FILTER (WHERE g.winner IS NULL) AS drawn, \
+         COUNT(*) FILTER (WHERE lower(g.winner) <> lower($1)) AS lost \
+         FROM games g LEFT JOIN openings o ON o.id = g.book_opening_id \
+         WHERE (lower(g.white) = lower($1) AND $2 <> 'black') \
+         OR (lower(g.black) = lower($1) AND $2 <> 'white') \
+         GROUP BY o.id, o.eco, o.name \
+         ORDER BY games DESC",
+    )
+    .bind::<Text, _>(player)
+    .bind::<Text, _>(color_param(color))
+    .load::<OpeningStat>(conn)
+}
+
+/// Adds up the stats of openings sharing a name at the given level. Grouped rows have no
+/// opening id and their eco is the range of codes they cover, like "B20-B99".
+pub fn group_opening_stats(stats: Vec<OpeningStat>, level: OpeningLevel) -> Vec<OpeningStat> {
+    if level == OpeningLevel::SubVariation {
+        return stats;
+    }
+
+    let mut groups: HashMap<Option<String>, (OpeningStat, Option<String>)> = HashMap::new();
+    for stat in stats {
+        let name = stat
+            .name
+            .as_deref()
+            .map(|name| OpeningName::parse(name).at(level));
+        let (group, max_eco) = groups.entry(name.clone()).or_insert_with(|| {
+            (
+                OpeningStat {
+                    opening_id: None,
+                    eco: stat.eco.clone(),
+                    name,
+                    games: 0,
+                    won: 0,
+                    drawn: 0,
+                    lost: 0,
+                },
+                stat.eco.clone(),
+            )
+        });
+        group.games += stat.games;
+        group.won += stat.won;
+        group.drawn += stat.drawn;
+        group.lost += stat.lost;
+        if stat.eco < group.eco {
+            group.eco = stat.eco.clone();
+        }
+        if stat.eco > *max_eco {
+            *max_eco = stat.eco;
+        }
+    }
+
+    let mut grouped = groups
+        .into_values()
+        .map(|(mut group, max_eco)| {
+            if let (Some(min), Some(max)) = (&group.eco, max_eco) {
+                if *min != max {
+                    group.eco = Some(format!("{}-{}", min, max));
+                }
+            }
+            group
+        })
+        .collect::<Vec<_>>();
+    grouped.sort_by_key(|group| Reverse(group.games));
+    grouped
+}
+
+#[derive(QueryableByName, Serialize, Debug)]
+pub struct DeviationStat {
+    #[sql_type = "Nullable<Integer>"]
+    pub opening_id: Option<i32>,
+    #[sql_type = "Nullable<Varchar>"]
+    pub eco: Option<String>,
+    #[sql_type = "Nullable<Varchar>"]
+    pub name: Option<String>,
+    #[sql_type = "BigInt"]
+    pub games: i64,
+    #[sql_type = "Nullable<Double>"]
+    pub avg_ply: Option<f64>,
+    #[sql_type = "Nullable<Double>"]
+    pub avg_swing: Option<f64>, //For the side that left book, negative means the deviation cost them
+    #[sql_type = "BigInt"]
+    pub won: i64,
+    #[sql_type = "BigInt"]
+    pub drawn: i64,
+    #[sql_type = "BigInt"]
+    pub lost: i64,
+}
+
+pub fn get_deviation_stats(
+    player: &str,
+    by_player: bool, //true - games where the player left book, false - where the opponent did
+    conn: &PgConnection,
+) -> QueryResult<Vec<DeviationStat>> {
+    let condition = if by_player {
+        "(lower(g.white) = lower($1) AND g.left_book_by = 'white') \
+         OR (lower(g.black) = lower($1) AND g.left_book_by = 'black')"
+    } else {
+        "(lower(g.white) = lower($1) AND g.left_book_by = 'black') \
+         OR (lower(g.black) = lower($1) AND g.left_book_by = 'white')"
+    };
+
+    sql_query(format!(
+        "SELECT o.id AS opening_id, o.eco, o.name, \
+         COUNT(*) AS games, \
+         AVG(g.left_book_ply)::float8 AS avg_ply, \
+         AVG(g.book_exit_swing)::float8 AS avg_swing, \
+         COUNT(*) FILTER (WHERE lower(g.winner) = lower($1)) AS won, \
+         COUNT(*) FILTER (WHERE g.winner IS NULL) AS drawn, \
+         COUNT(*) FILTER (WHERE lower(g.winner) <> lower($1)) AS lost \
+         FROM games g LEFT JOIN openings o ON o.id = g.book_opening_id \
+         WHERE {} \
+         GROUP BY o.id, o.eco, o.name \
+         ORDER BY games DESC",
+        condition
+    ))
+    .bind::<Text, _>(player)
+    .load::<DeviationStat>(conn)
+}
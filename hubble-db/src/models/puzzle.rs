@@ -0,0 +1,55 @@
+use serde::{Deserialize, Serialize};
+
+use diesel::pg::PgConnection;
+use diesel::prelude::*;
+
+use crate::models::player_key;
+use crate::schema::puzzles;
+
+#[derive(Insertable, Queryable, Deserialize, Serialize, Clone, Debug, PartialEq, Eq)]
+#[table_name = "puzzles"]
+pub struct Puzzle {
+    pub game_id: String,
+    pub ply: i32, //Ply of the blunder the puzzle comes from
+    pub player: String, //Lowercased, see player_key
+    pub fen: String, //Position before the blunder, the player to move
+    pub previous_fen: Option<String>, //Position before the opponent's last move, none at the first ply
+    pub previous_move: Option<String>,
+    pub solution: Vec<String>,     //Uci moves from the puzzle position
+    pub played: String,            //The blunder played in the game instead
+    pub best_score: i32,           //Centipawns for the player after the solution
+    pub second_score: Option<i32>, //Best score of any other move, none when the solution is forced
+    pub themes: Vec<String>,       //Lichess puzzle themes
+    pub rating: Option<i32>,       //Rating of the player in the game
+}
+
+/// Replaces the player's stored puzzles from the given games, games without puzzles are cleared.
+/// Puzzles of the opponent in the same games are kept.
+pub fn replace_puzzles(
+    conn: &PgConnection,
+    player: &str,
+    game_ids: &[String],
+    rows: &[Puzzle],
+) -> Result<usize, diesel::result::Error> {
+    conn.transaction(|| {
+        diesel::delete(
+            puzzles::table
+                .filter(puzzles::player.eq(player_key(player)))
+                .filter(puzzles::game_id.eq_any(game_ids)),
+        )
+        .execute(conn)?;
+        if rows.is_empty() {
+            return Ok(0);
+        }
+        diesel::insert_into(puzzles::table)
+            .values(rows)
+            .execute(conn)
+    })
+}
+
+pub fn get_puzzles(player: &str, conn: &PgConnection) -> QueryResult<Vec<Puzzle>> {
+    puzzles::table
+        .filter(puzzles::player.eq(player_key(player)))
+        .order((puzzles::game_id, puzzles::ply))
+        .load::<Puzzle>(conn)
+}
@@ -0,0 +1,166 @@
+use chrono::NaiveDateTime;
+use serde::{Deserialize, Serialize};
+use std::str::FromStr;
+
+use diesel::pg::upsert::excluded;
+use diesel::pg::PgConnection;
+use diesel::prelude::*;
+
+use crate::models::player_key;
+use crate::schema::{training_attempts, training_items};
+
+#[derive(Deserialize, Serialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum TrainingKind {
+    Blunder,    //A stored puzzle, the position before one of the player's blunders
+    Repertoire, //A position of the player's repertoire, answered with their usual move
+}
+
+impl TrainingKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            TrainingKind::Blunder => "blunder",
+            TrainingKind::Repertoire => "repertoire",
+        }
+    }
+}
+
+impl FromStr for TrainingKind {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "blunder" => Ok(TrainingKind::Blunder),
+            "repertoire" => Ok(TrainingKind::Repertoire),
+            _ => Err(()),
+        }
+    }
+}
+
+#[derive(Queryable, Identifiable, Serialize, Clone, Debug, PartialEq)]
+#[table_name = "training_items"]
+pub struct TrainingItem {
+    pub id: i32,
+    pub player: String, //Lowercased, see player_key
+    pub kind: String,
+    pub fen: String,             //The player to move
+    pub answers: Vec<String>,    //Uci moves counted as correct
+    pub game_id: Option<String>, //Game of the blunder, none for repertoire positions
+    pub repetitions: i32,        //Correct answers in a row
+    pub interval_days: i32,
+    pub ease: f64,
+    pub due_at: NaiveDateTime,
+}
+
+#[derive(Insertable, Debug)]
+#[table_name = "training_items"]
+pub struct NewTrainingItem {
+    pub player: String,
+    pub kind: String,
+    pub fen: String,
+    pub answers: Vec<String>,
+    pub game_id: Option<String>,
+}
+
+#[derive(Insertable, Debug)]
+#[table_name = "training_attempts"]
+pub struct NewTrainingAttempt {
+    pub item_id: i32,
+    pub submitted: String, //Uci, whatever notation it was sent in
+    pub correct: bool,
+    pub time_ms: i32,
+    pub attempted_at: NaiveDateTime,
+}
+
+/// When an item comes back, kept on the item and updated after every attempt.
+#[derive(Serialize, Clone, Copy, Debug, PartialEq)]
+pub struct Schedule {
+    pub repetitions: i32,
+    pub interval_days: i32,
+    pub ease: f64,
+    pub due_at: NaiveDateTime,
+}
+
+impl TrainingItem {
+    pub fn schedule(&self) -> Schedule {
+        Schedule {
+            repetitions: self.repetitions,
+            interval_days: self.interval_days,
+            ease: self.ease,
+            due_at: self.due_at,
+        }
+    }
+}
+
+/// Adds new items and refreshes the answers of known ones, their schedule is kept.
+pub fn upsert_training_items(
+    rows: &[NewTrainingItem],
+    conn: &PgConnection,
+) -> Result<usize, diesel::result::Error> {
+    if rows.is_empty() {
+        return Ok(0);
+    }
+
+    diesel::insert_into(training_items::table)
+        .values(rows)
+        .on_conflict((
+            training_items::player,
+            training_items::kind,
+            training_items::fen,
+        ))
+        .do_update()
+        .set((
+            training_items::answers.eq(excluded(training_items::answers)),
+            training_items::game_id.eq(excluded(training_items::game_id)),
+        ))
+        .execute(conn)
+}
+
+pub fn get_training_item(id: i32, conn: &PgConnection) -> QueryResult<Option<TrainingItem>> {
+    training_items::table
+        .find(id)
+        .first::<TrainingItem>(conn)
+        .optional()
+}
+
+/// The item of the player that has been due the longest, none when nothing is due.
+pub fn next_due_item(
+    player: &str,
+    kind: Option<TrainingKind>,
+    now: NaiveDateTime,
+    conn: &PgConnection,
+) -> QueryResult<Option<TrainingItem>> {
+    let mut query = training_items::table
+        .filter(training_items::player.eq(player_key(player)))
+        .filter(training_items::due_at.le(now))
+        .into_boxed();
+    if let Some(kind) = kind {
+        query = query.filter(training_items::kind.eq(kind.as_str()));
+    }
+
+    query
+        .order((training_items::due_at, training_items::id))
+        .first::<TrainingItem>(conn)
+        .optional()
+}
+
+/// Stores the attempt and moves the item to its new schedule.
+pub fn record_attempt(
+    attempt: &NewTrainingAttempt,
+    schedule: &Schedule,
+    conn: &PgConnection,
+) -> QueryResult<TrainingItem> {
+    conn.transaction(|| {
+        diesel::insert_into(training_attempts::table)
+            .values(attempt)
+            .execute(conn)?;
+        diesel::update(training_items::table.find(attempt.item_id))
+            .set((
+                training_items::repetitions.eq(schedule.repetitions),
+                training_items::interval_days.eq(schedule.interval_days),
+                training_items::ease.eq(schedule.ease),
+                training_items::due_at.eq(schedule.due_at),
+            ))
+            .get_result::<TrainingItem>(conn)
+    })
+}
@@ -1,3 +1,14 @@
+table! {
+    blunders (game_id, ply) {
+        game_id -> Varchar,
+        ply -> Int4,
+        uci -> Varchar,
+        san -> Varchar,
+        piece -> Varchar,
+        motifs -> Array<Varchar>,
+    }
+}
+
 table! {
     games (id) {
         id -> Varchar,
@@ -12,6 +23,17 @@ table! {
         middle_game -> Nullable<Int4>,
         end_game -> Nullable<Int4>,
         blunders -> Jsonb,
+        analysis_version -> Int4,
+        engine -> Nullable<Varchar>,
+        engine_nodes -> Nullable<Int4>,
+        analysed_at -> Nullable<Timestamp>,
+        played_at -> Nullable<Timestamp>,
+        time_control -> Nullable<Varchar>,
+        speed -> Nullable<Varchar>,
+        book_opening_id -> Nullable<Int4>,
+        left_book_ply -> Nullable<Int4>,
+        left_book_by -> Nullable<Varchar>,
+        book_exit_swing -> Nullable<Int4>,
     }
 }
 
@@ -21,6 +43,52 @@ table! {
         eco -> Varchar,
         name -> Varchar,
         pgn -> Varchar,
+        uci -> Nullable<Varchar>,
+        epd -> Nullable<Varchar>,
+        ply -> Nullable<Int4>,
+    }
+}
+
+table! {
+    puzzles (game_id, ply) {
+        game_id -> Varchar,
+        ply -> Int4,
+        player -> Varchar,
+        fen -> Varchar,
+        previous_fen -> Nullable<Varchar>,
+        previous_move -> Nullable<Varchar>,
+        solution -> Array<Varchar>,
+        played -> Varchar,
+        best_score -> Int4,
+        second_score -> Nullable<Int4>,
+        themes -> Array<Varchar>,
+        rating -> Nullable<Int4>,
+    }
+}
+
+table! {
+    training_attempts (id) {
+        id -> Int4,
+        item_id -> Int4,
+        submitted -> Varchar,
+        correct -> Bool,
+        time_ms -> Int4,
+        attempted_at -> Timestamp,
+    }
+}
+
+table! {
+    training_items (id) {
+        id -> Int4,
+        player -> Varchar,
+        kind -> Varchar,
+        fen -> Varchar,
+        answers -> Array<Varchar>,
+        game_id -> Nullable<Varchar>,
+        repetitions -> Int4,
+        interval_days -> Int4,
+        ease -> Float8,
+        due_at -> Timestamp,
     }
 }
 
@@ -31,8 +99,18 @@ table! {
     }
 }
 
+joinable!(blunders -> games (game_id));
+joinable!(games -> openings (book_opening_id));
+joinable!(puzzles -> games (game_id));
+joinable!(training_attempts -> training_items (item_id));
+joinable!(training_items -> games (game_id));
+
 allow_tables_to_appear_in_same_query!(
+    blunders,
     games,
     openings,
+    puzzles,
+    training_attempts,
+    training_items,
     users,
 );
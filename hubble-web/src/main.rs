@@ -5,22 +5,71 @@ extern crate rocket;
 extern crate dotenv;
 
 use crate::routes::*;
+use std::sync::Arc;
 
-#[launch]
-fn rocket() -> _ {
+#[rocket::main]
+async fn main() {
     dotenv::from_filename("../.env").ok();
 
-    rocket::build()
-        .manage(hubble_db::establish_connection())
+    let pool = match hubble_db::establish_connection() {
+        Ok(pool) => pool,
+        Err(e) => {
+            eprintln!("{}", e);
+            std::process::exit(1);
+        }
+    };
+
+    if let Err(e) = hubble_db::run_migrations(&pool) {
+        eprintln!("{}", e);
+        std::process::exit(1);
+    }
+
+    let db = hubble_db::Database::new(pool);
+    let classifier = match hubble::analysis::opening::load_classifier(&db).await {
+        Ok(classifier) => Arc::new(classifier),
+        Err(e) => {
+            eprintln!("{}", e);
+            std::process::exit(1);
+        }
+    };
+
+    let result = rocket::build()
+        .manage(db)
+        .manage(classifier)
         .mount(
             "/api",
             routes![
                 analyse::analyse,
                 analyse::analyse_player,
                 blunder::blunder,
+                blunder::blunder_profile,
                 game::games,
+                health::health,
+                opening::opening_position,
                 opening::opening_player,
+                opening::opening_stats,
+                opening::opening_deviations,
+                performance::player_performance,
+                repertoire::repertoire,
+                repertoire::repertoire_pgn,
+                repertoire::repertoire_gaps,
+                preparation::prepare_opponent,
+                preparation::fetch_opponent,
+                puzzle::puzzles,
+                puzzle::generate,
+                puzzle::puzzles_csv,
+                puzzle::puzzles_pgn,
+                training::sync,
+                training::next,
+                training::attempt,
                 opening::find_opening
             ],
         )
+        .launch()
+        .await;
+
+    if let Err(e) = result {
+        eprintln!("{}", e);
+        std::process::exit(1);
+    }
 }
@@ -1,31 +1,40 @@
 use hubble_db::models::game::Game;
-use hubble_db::{pg_pool_handler, PgPool};
+use hubble_db::Database;
 use rocket::http::Status;
 use rocket::serde::json::Json;
 use rocket::State;
 
+use crate::routes::db_status;
+use hubble::analysis::OpeningClassifier;
 use hubble::lichess;
+use std::sync::Arc;
 
 #[get("/analyse/match/<id>")]
-pub async fn analyse(dbpool: &State<PgPool>, id: &str) -> Result<Json<Game>, Status> {
-    let connection = pg_pool_handler(dbpool).unwrap();
-    match lichess::analyse_lichess_game(connection, id).await {
+pub async fn analyse(
+    db: &State<Database>,
+    classifier: &State<Arc<OpeningClassifier>>,
+    id: &str,
+) -> Result<Json<Game>, Status> {
+    match lichess::analyse_lichess_game(db, classifier, id).await {
         Ok(game) => Ok(Json(game)),
         Err(e) => match e {
             lichess::AnalysisErrors::NotFound => Err(Status::NotFound),
+            lichess::AnalysisErrors::Database(e) => Err(db_status(&e)),
             _ => Err(Status::InternalServerError),
         },
     }
 }
 
-#[get("/analyse/player/<player>")]
+#[get("/analyse/player/<player>?<num_games>")]
 pub async fn analyse_player(
-    dbpool: &State<PgPool>,
+    db: &State<Database>,
+    classifier: &State<Arc<OpeningClassifier>>,
     player: String,
+    num_games: Option<usize>,
 ) -> Result<Json<Vec<Game>>, Status> {
-    let connection = pg_pool_handler(dbpool).unwrap();
-    match lichess::analyse_player(connection, &player).await {
+    match lichess::analyse_player(db, classifier, &player, num_games.unwrap_or(10)).await {
         Ok(games) => Ok(Json(games)),
+        Err(lichess::AnalysisErrors::Database(e)) => Err(db_status(&e)),
         Err(_) => Err(Status::InternalServerError),
     }
 }
@@ -1,22 +1,34 @@
-use hubble_db::{pg_pool_handler, PgPool};
+use hubble_db::models::blunder_stats::PlayerBlunderProfile;
+use hubble_db::Database;
 use rocket::http::Status;
 use rocket::serde::json::Json;
 use rocket::State;
 
+use crate::routes::db_status;
 use hubble::analysis::blunder::find_blunder;
-use hubble_db::models::game::get_game;
 
 #[get("/blunder/<id>")]
-pub fn blunder(
-    dbpool: &State<PgPool>,
+pub async fn blunder(
+    db: &State<Database>,
     id: &str,
 ) -> Result<Json<Vec<(usize, String)>>, Status> {
-    let conn = pg_pool_handler(dbpool).unwrap();
-    match get_game(id, &conn) {
-        Some(game) => {
+    match db.get_game(id).await {
+        Ok(Some(game)) => {
             let blunders = find_blunder(&game);
             Ok(Json(blunders))
         }
-        None => Err(Status::NotFound),
+        Ok(None) => Err(Status::NotFound),
+        Err(e) => Err(db_status(&e)),
+    }
+}
+
+#[get("/blunders/<player>")]
+pub async fn blunder_profile(
+    db: &State<Database>,
+    player: &str,
+) -> Result<Json<PlayerBlunderProfile>, Status> {
+    match db.get_blunder_profile(player).await {
+        Ok(profile) => Ok(Json(profile)),
+        Err(e) => Err(db_status(&e)),
     }
 }
@@ -1,12 +1,87 @@
-use hubble_db::{models::game, pg_pool_handler, PgPool};
+use chrono::{NaiveDate, NaiveDateTime};
+use hubble_db::models::game_filter::{GameFilter, GamePage};
+use hubble_db::Database;
 use rocket::http::Status;
+use rocket::serde::json::Json;
 use rocket::State;
 
-#[get("/games")]
-pub fn games(dbpool: &State<PgPool>) -> Result<String, Status> {
-    let conn = pg_pool_handler(dbpool).unwrap();
-    match serde_json::to_string(&game::get_games(&conn)) {
-        Ok(s) => Ok(s),
-        Err(_) => Err(Status::InternalServerError),
+use crate::routes::db_status;
+
+const MAX_LIMIT: i64 = 200;
+
+#[derive(FromForm, Debug)]
+pub struct GameQuery {
+    player: Option<String>,
+    color: Option<String>,
+    result: Option<String>,
+    eco: Option<String>,
+    min_rating: Option<i32>,
+    max_rating: Option<i32>,
+    from: Option<String>, //YYYY-MM-DD
+    to: Option<String>,
+    time_control: Option<String>,
+    sort: Option<String>,
+    order: Option<String>,
+    limit: Option<i64>,
+    offset: Option<i64>,
+}
+
+fn parse_date(date: &Option<String>) -> Result<Option<NaiveDateTime>, Status> {
+    match date {
+        Some(d) => match NaiveDate::parse_from_str(d, "%Y-%m-%d") {
+            Ok(parsed) => Ok(parsed.and_hms_opt(0, 0, 0)),
+            Err(_) => Err(Status::BadRequest),
+        },
+        None => Ok(None),
+    }
+}
+
+fn parse_option<T: std::str::FromStr>(value: &Option<String>) -> Result<Option<T>, Status> {
+    match value {
+        Some(v) => match v.parse::<T>() {
+            Ok(parsed) => Ok(Some(parsed)),
+            Err(_) => Err(Status::BadRequest),
+        },
+        None => Ok(None),
+    }
+}
+
+impl GameQuery {
+    fn into_filter(self) -> Result<GameFilter, Status> {
+        let default = GameFilter::default();
+        //The color is the player's, there is nothing to apply it to without one
+        if self.color.is_some() && self.player.is_none() {
+            return Err(Status::BadRequest);
+        }
+
+        Ok(GameFilter {
+            color: parse_option(&self.color)?,
+            result: parse_option(&self.result)?,
+            from: parse_date(&self.from)?,
+            to: parse_date(&self.to)?,
+            sort: parse_option(&self.sort)?.unwrap_or(default.sort),
+            descending: match self.order.as_deref() {
+                Some("asc") => false,
+                Some("desc") | None => true,
+                Some(_) => return Err(Status::BadRequest),
+            },
+            limit: self.limit.unwrap_or(default.limit).clamp(1, MAX_LIMIT),
+            offset: self.offset.unwrap_or(default.offset).max(0),
+            player: self.player,
+            exclude_player: None,
+            eco: self.eco,
+            min_rating: self.min_rating,
+            max_rating: self.max_rating,
+            time_control: self.time_control,
+        })
+    }
+}
+
+#[get("/games?<query..>")]
+pub async fn games(db: &State<Database>, query: GameQuery) -> Result<Json<GamePage>, Status> {
+    let filter = query.into_filter()?;
+    match db.find_games(filter).await {
+        Ok(page) => Ok(Json(page)),
+        Err(e) => Err(db_status(&e)),
     }
 }
@@ -0,0 +1,29 @@
+use hubble_db::Database;
+use rocket::http::Status;
+use rocket::serde::json::Json;
+use rocket::State;
+use serde::Serialize;
+
+use hubble::analysis::engine_available;
+
+#[derive(Debug, Serialize)]
+pub struct Health {
+    database: bool,
+    engine: bool,
+}
+
+#[get("/health")]
+pub async fn health(db: &State<Database>) -> (Status, Json<Health>) {
+    let health = Health {
+        database: db.ping().await.is_ok(),
+        engine: engine_available(),
+    };
+
+    let status = if health.database && health.engine {
+        Status::Ok
+    } else {
+        Status::ServiceUnavailable
+    };
+
+    (status, Json(health))
+}
@@ -1,4 +1,21 @@
 pub mod analyse;
 pub mod blunder;
 pub mod game;
+pub mod health;
 pub mod opening;
+pub mod performance;
+pub mod preparation;
+pub mod puzzle;
+pub mod repertoire;
+pub mod training;
+
+use hubble_db::DbError;
+use rocket::http::Status;
+
+pub fn db_status(e: &DbError) -> Status {
+    //An exhausted pool or an unreachable database is temporary, the client can retry
+    match e {
+        DbError::Pool(_) => Status::ServiceUnavailable,
+        _ => Status::InternalServerError,
+    }
+}
@@ -1,49 +1,49 @@
 use rocket::http::Status;
 use rocket::State;
 
-use hubble_db::models::{get_openings, Opening};
-use hubble_db::{pg_pool_handler, PgPool};
+use hubble_db::models::Opening;
 use rocket::serde::json::Json;
 use serde::{Deserialize, Serialize};
 
+use hubble::analysis::opening::fen_to_epd;
 use hubble::analysis::opening_tree::MoveEntry;
+use hubble::analysis::OpeningClassifier;
 use hubble::lichess;
 use hubble::lichess::AnalysisErrors;
 use std::collections::HashMap;
+use std::sync::Arc;
+
+use crate::routes::db_status;
+use hubble_db::models::game_filter::Color;
+use hubble_db::models::opening_name::OpeningLevel;
+use hubble_db::models::opening_stats::{DeviationStat, OpeningStat};
+use hubble_db::Database;
 
 #[derive(Debug, PartialEq, Eq, Deserialize, Serialize)]
 pub struct OpeningRequest {
     moves: Vec<String>,
-    eco: String,
+    eco: Option<String>, //Not needed anymore, the opening is found from the positions
 }
 
 #[post("/opening", format = "json", data = "<opening>")]
 pub fn find_opening(
-    dbpool: &State<PgPool>,
+    classifier: &State<Arc<OpeningClassifier>>,
     opening: Json<OpeningRequest>,
 ) -> Result<Json<Opening>, Status> {
-    let conn = pg_pool_handler(dbpool).unwrap();
-    let moves = &opening.moves.to_vec();
-
-    if let Some(openings) = get_openings(&conn, &opening.eco) {
-        let mut longest_match = 0;
-        let mut longest_opening = None;
-
-        for op in openings {
-            let length = hubble::analysis::opening::match_length(&op, &moves);
-
-            if length > longest_match {
-                longest_match = length;
-                longest_opening = Some(op);
-            }
-        }
-
-        match longest_opening {
-            Some(o) => Ok(Json(o)),
-            None => Err(Status::NotFound),
-        }
-    } else {
-        Err(Status::NotFound)
+    match classifier.classify_uci(&opening.moves) {
+        Some(classification) => Ok(Json(classification.opening.clone())),
+        None => Err(Status::NotFound),
+    }
+}
+
+#[get("/opening/position?<fen>")]
+pub async fn opening_position(db: &State<Database>, fen: &str) -> Result<Json<Opening>, Status> {
+    let epd = fen_to_epd(fen).ok_or(Status::BadRequest)?;
+
+    match db.find_opening_by_position(&epd).await {
+        Ok(Some(opening)) => Ok(Json(opening)),
+        Ok(None) => Err(Status::NotFound),
+        Err(e) => Err(db_status(&e)),
     }
 }
 
@@ -57,3 +57,40 @@ pub async fn opening_player(player: &str) -> Result<Json<HashMap<String, Vec<Mov
         Err(_) => Err(Status::InternalServerError),
     }
 }
+
+#[get("/opening/<player>/stats?<color>&<level>")]
+pub async fn opening_stats(
+    db: &State<Database>,
+    player: &str,
+    color: Option<&str>,
+    level: Option<&str>, //family, variation or sub_variation, the full name
+) -> Result<Json<Vec<OpeningStat>>, Status> {
+    let color = match color {
+        Some(c) => Some(c.parse::<Color>().map_err(|_| Status::BadRequest)?),
+        None => None,
+    };
+    let level = match level {
+        Some(l) => l.parse::<OpeningLevel>().map_err(|_| Status::BadRequest)?,
+        None => OpeningLevel::SubVariation,
+    };
+
+    match db.get_opening_stats(player, color, level).await {
+        Ok(stats) => Ok(Json(stats)),
+        Err(e) => Err(db_status(&e)),
+    }
+}
+
+#[get("/opening/<player>/deviations?<opponent>")]
+pub async fn opening_deviations(
+    db: &State<Database>,
+    player: &str,
+    opponent: Option<bool>, //true - games where the opponent left book instead
+) -> Result<Json<Vec<DeviationStat>>, Status> {
+    match db
+        .get_deviation_stats(player, !opponent.unwrap_or(false))
+        .await
+    {
+        Ok(stats) => Ok(Json(stats)),
+        Err(e) => Err(db_status(&e)),
+    }
+}
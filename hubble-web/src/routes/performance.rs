@@ -0,0 +1,28 @@
+use hubble::analysis::stats::{stored_performance, Performance, PerformanceGroup};
+use hubble_db::Database;
+use rocket::http::Status;
+use rocket::serde::json::Json;
+use rocket::State;
+
+use crate::routes::db_status;
+
+#[get("/performance/<player>?<group>&<max_games>")]
+pub async fn player_performance(
+    db: &State<Database>,
+    player: &str,
+    group: Option<&str>, //opening, color, time_control or month
+    max_games: Option<i64>,
+) -> Result<Json<Vec<Performance>>, Status> {
+    let group = match group {
+        Some(g) => g
+            .parse::<PerformanceGroup>()
+            .map_err(|_| Status::BadRequest)?,
+        None => PerformanceGroup::Opening,
+    };
+    let max_games = max_games.unwrap_or(1000).clamp(1, 10_000);
+
+    match stored_performance(db, player, group, max_games).await {
+        Ok(performances) => Ok(Json(performances)),
+        Err(e) => Err(db_status(&e)),
+    }
+}
@@ -0,0 +1,58 @@
+use hubble::analysis::engine_available;
+use hubble::analysis::preparation::{prepare, PreparationOptions, PreparationReport};
+use hubble::analysis::OpeningClassifier;
+use hubble::lichess::analyse_player;
+use hubble_db::Database;
+use rocket::http::Status;
+use rocket::serde::json::Json;
+use rocket::State;
+use std::sync::Arc;
+
+use crate::routes::db_status;
+
+#[get("/prepare/<opponent>?<num_games>&<depth>")]
+pub async fn prepare_opponent(
+    db: &State<Database>,
+    classifier: &State<Arc<OpeningClassifier>>,
+    opponent: &str,
+    num_games: Option<i64>,
+    depth: Option<usize>,
+) -> Result<Json<PreparationReport>, Status> {
+    let defaults = PreparationOptions::default();
+    let options = PreparationOptions {
+        line_depth: depth.unwrap_or(defaults.line_depth).min(30),
+        ..defaults
+    };
+    let num_games = num_games.unwrap_or(50).clamp(1, 300);
+
+    match prepare(db, classifier, opponent, num_games, options).await {
+        //Nothing stored yet, /prepare/<opponent>/fetch gets the games
+        Ok(report) if report.games == 0 => Err(Status::NotFound),
+        Ok(report) => Ok(Json(report)),
+        Err(e) => Err(db_status(&e)),
+    }
+}
+
+#[post("/prepare/<opponent>/fetch?<num_games>")]
+pub async fn fetch_opponent(
+    db: &State<Database>,
+    classifier: &State<Arc<OpeningClassifier>>,
+    opponent: String,
+    num_games: Option<usize>,
+) -> Status {
+    if !engine_available() {
+        return Status::ServiceUnavailable;
+    }
+    let num_games = num_games.unwrap_or(50).min(300);
+
+    //Analysing the games takes minutes, the report picks them up as they are stored
+    let db = db.inner().clone();
+    let classifier = classifier.inner().clone();
+    tokio::spawn(async move {
+        match analyse_player(&db, &classifier, &opponent, num_games).await {
+            Ok(games) => println!("Stored {} games of {}", games.len(), opponent),
+            Err(e) => eprintln!("Could not fetch the games of {}: {:?}", opponent, e),
+        }
+    });
+    Status::Accepted
+}
@@ -0,0 +1,52 @@
+use hubble_db::models::puzzle::Puzzle;
+use hubble_db::Database;
+use rocket::http::Status;
+use rocket::serde::json::Json;
+use rocket::State;
+
+use crate::routes::db_status;
+use hubble::analysis::engine_available;
+use hubble::analysis::puzzle::{generate_puzzles, puzzles_to_csv};
+use hubble::pgn::puzzles_to_pgn;
+
+#[get("/puzzles/<player>")]
+pub async fn puzzles(db: &State<Database>, player: &str) -> Result<Json<Vec<Puzzle>>, Status> {
+    match db.get_puzzles(player).await {
+        Ok(puzzles) => Ok(Json(puzzles)),
+        Err(e) => Err(db_status(&e)),
+    }
+}
+
+#[post("/puzzles/<player>/generate?<max_games>")]
+pub async fn generate(db: &State<Database>, player: String, max_games: Option<i64>) -> Status {
+    if !engine_available() {
+        return Status::ServiceUnavailable;
+    }
+    let max_games = max_games.unwrap_or(100).clamp(1, 1000);
+
+    //Searching every blunder takes minutes, the puzzles show up in /puzzles/<player> once stored
+    let db = db.inner().clone();
+    tokio::spawn(async move {
+        match generate_puzzles(&db, &player, max_games).await {
+            Ok(stored) => println!("Stored {} puzzles for {}", stored, player),
+            Err(e) => eprintln!("Could not generate puzzles for {}: {}", player, e),
+        }
+    });
+    Status::Accepted
+}
+
+#[get("/puzzles/<player>/csv")]
+pub async fn puzzles_csv(db: &State<Database>, player: &str) -> Result<String, Status> {
+    match db.get_puzzles(player).await {
+        Ok(puzzles) => Ok(puzzles_to_csv(&puzzles)),
+        Err(e) => Err(db_status(&e)),
+    }
+}
+
+#[get("/puzzles/<player>/pgn")]
+pub async fn puzzles_pgn(db: &State<Database>, player: &str) -> Result<String, Status> {
+    match db.get_puzzles(player).await {
+        Ok(puzzles) => Ok(puzzles_to_pgn(&puzzles)),
+        Err(e) => Err(db_status(&e)),
+    }
+}
@@ -0,0 +1,75 @@
+use hubble_db::models::game_filter::Color;
+use hubble_db::Database;
+use rocket::http::Status;
+use rocket::serde::json::Json;
+use rocket::State;
+
+use crate::routes::db_status;
+use hubble::analysis::gaps::{player_gaps, GapOptions, RepertoireGap};
+use hubble::analysis::repertoire::{player_repertoire, Repertoire};
+use hubble::pgn::repertoire_to_pgn;
+
+#[get("/repertoire/<player>/<color>?<depth>&<max_games>")]
+pub async fn repertoire(
+    db: &State<Database>,
+    player: &str,
+    color: &str,
+    depth: Option<usize>,
+    max_games: Option<i64>,
+) -> Result<Json<Repertoire>, Status> {
+    let color = color.parse::<Color>().map_err(|_| Status::BadRequest)?;
+    let depth = depth.unwrap_or(20);
+    let max_games = max_games.unwrap_or(1000).clamp(1, 10_000);
+
+    match player_repertoire(db, player, color, depth, max_games).await {
+        Ok(repertoire) => Ok(Json(repertoire)),
+        Err(e) => Err(db_status(&e)),
+    }
+}
+
+#[get("/repertoire/<player>/<color>/pgn?<depth>&<max_games>&<min_games>")]
+pub async fn repertoire_pgn(
+    db: &State<Database>,
+    player: &str,
+    color: &str,
+    depth: Option<usize>,
+    max_games: Option<i64>,
+    min_games: Option<u32>,
+) -> Result<String, Status> {
+    let color = color.parse::<Color>().map_err(|_| Status::BadRequest)?;
+    let depth = depth.unwrap_or(20);
+    let max_games = max_games.unwrap_or(1000).clamp(1, 10_000);
+
+    match player_repertoire(db, player, color, depth, max_games).await {
+        Ok(repertoire) => Ok(repertoire_to_pgn(
+            &repertoire,
+            min_games.unwrap_or(2),
+            depth,
+        )),
+        Err(e) => Err(db_status(&e)),
+    }
+}
+
+#[get("/repertoire/<player>/<color>/gaps?<depth>&<min_games>&<min_score>")]
+pub async fn repertoire_gaps(
+    db: &State<Database>,
+    player: &str,
+    color: &str,
+    depth: Option<usize>,
+    min_games: Option<u32>,
+    min_score: Option<f64>,
+) -> Result<Json<Vec<RepertoireGap>>, Status> {
+    let color = color.parse::<Color>().map_err(|_| Status::BadRequest)?;
+    let defaults = GapOptions::default();
+    let options = GapOptions {
+        max_ply: depth.unwrap_or(defaults.max_ply).min(40),
+        min_games: min_games.unwrap_or(defaults.min_games),
+        min_score: min_score.unwrap_or(defaults.min_score),
+        ..defaults
+    };
+
+    match player_gaps(db, player, color, None, options).await {
+        Ok(gaps) => Ok(Json(gaps)),
+        Err(e) => Err(db_status(&e)),
+    }
+}
@@ -0,0 +1,75 @@
+use hubble_db::models::training::TrainingKind;
+use hubble_db::Database;
+use rocket::http::Status;
+use rocket::serde::json::Json;
+use rocket::State;
+use serde::Deserialize;
+
+use crate::routes::db_status;
+use hubble::training::{
+    next_position, submit_attempt, sync_training_items, AttemptResult, TrainingError,
+    TrainingOptions, TrainingPosition,
+};
+
+#[derive(Debug, Deserialize)]
+pub struct AttemptRequest {
+    item_id: i32,
+    #[serde(rename = "move")]
+    submitted: String, //Uci or san
+    time_ms: i32,
+}
+
+#[post("/training/<player>/sync?<depth>&<max_games>&<min_games>")]
+pub async fn sync(
+    db: &State<Database>,
+    player: &str,
+    depth: Option<usize>,
+    max_games: Option<i64>,
+    min_games: Option<u32>,
+) -> Result<Json<usize>, Status> {
+    let defaults = TrainingOptions::default();
+    let options = TrainingOptions {
+        max_ply: depth.unwrap_or(defaults.max_ply).min(40),
+        max_games: max_games.unwrap_or(defaults.max_games).clamp(1, 10_000),
+        min_games: min_games.unwrap_or(defaults.min_games),
+    };
+
+    match sync_training_items(db, player, options).await {
+        Ok(items) => Ok(Json(items)),
+        Err(e) => Err(db_status(&e)),
+    }
+}
+
+#[get("/training/<player>/next?<kind>")]
+pub async fn next(
+    db: &State<Database>,
+    player: &str,
+    kind: Option<&str>, //blunder or repertoire, either when not given
+) -> Result<Json<TrainingPosition>, Status> {
+    let kind = match kind {
+        Some(k) => Some(k.parse::<TrainingKind>().map_err(|_| Status::BadRequest)?),
+        None => None,
+    };
+
+    match next_position(db, player, kind).await {
+        Ok(Some(position)) => Ok(Json(position)),
+        Ok(None) => Err(Status::NotFound),
+        Err(e) => Err(db_status(&e)),
+    }
+}
+
+#[post("/training/attempt", format = "json", data = "<attempt>")]
+pub async fn attempt(
+    db: &State<Database>,
+    attempt: Json<AttemptRequest>,
+) -> Result<Json<AttemptResult>, Status> {
+    let time_ms = attempt.time_ms.max(0);
+
+    match submit_attempt(db, attempt.item_id, &attempt.submitted, time_ms).await {
+        Ok(result) => Ok(Json(result)),
+        Err(TrainingError::NotFound) => Err(Status::NotFound),
+        Err(TrainingError::IllegalMove) => Err(Status::BadRequest),
+        Err(TrainingError::InvalidPosition) => Err(Status::InternalServerError),
+        Err(TrainingError::Database(e)) => Err(db_status(&e)),
+    }
+}
@@ -1,104 +1,167 @@
+use crate::analysis::book_exit::mark_book_exit;
+use crate::analysis::motif::classify_blunder;
+use crate::analysis::phase::{divide, group_blunders, PhaseOptions};
+use crate::analysis::OpeningClassifier;
 use async_trait::async_trait;
-use hubble_db::models::game::{Game, Blunders};
+use chrono::{NaiveDate, NaiveTime};
+use hubble_db::models::game::{AnalysisProfile, Game};
 use pgn_reader::{AsyncVisitor, RawHeader, SanPlus, Skip};
-use shakmaty::Rank;
-use shakmaty::{
-    bitboard::Bitboard, fen::Fen, uci::Uci, CastlingMode, Chess, Move, Position, Setup,
-};
+use shakmaty::{fen::Fen, uci::Uci, CastlingMode, Chess, Move, Position};
+use std::collections::BTreeMap;
 use std::sync::Arc;
-use uciengine::analysis::Score;
+use tokio::sync::broadcast::error::TryRecvError;
+use uciengine::analysis::{AnalysisInfo, Score};
 use uciengine::uciengine::{GoJob, UciEngine};
 
-async fn get_engine() -> Arc<UciEngine> {
-    let engine = UciEngine::new("./stockfish");
+// Bump when the scores or blunder rules change so stored games get re-analysed, phases are
+// recomputed without the engine by phase::recompute_phases
+pub const ANALYSIS_VERSION: i32 = 3;
+pub const ENGINE_NAME: &str = "stockfish 14.1";
+pub const ENGINE_NODES: i32 = 1000 * 1000;
+pub const ENGINE_PATH: &str = "./stockfish";
+
+pub fn current_profile() -> AnalysisProfile {
+    AnalysisProfile::new(ENGINE_NAME, ENGINE_NODES, ANALYSIS_VERSION)
+}
+
+pub fn engine_available() -> bool {
+    std::path::Path::new(ENGINE_PATH).is_file()
+}
+
+pub(crate) async fn get_engine() -> Arc<UciEngine> {
+    let engine = UciEngine::new(ENGINE_PATH);
 
     let setup_job = GoJob::new().uci_opt("Hash", 8192).uci_opt("Threads", 10);
     let _result = engine.check_ready(setup_job).await.unwrap();
     engine
 }
 
-pub async fn eval_move(pos: &Chess, m: &Move, engine: &Arc<UciEngine>) -> i32 {
+/// Score after the move together with the engine's best line from there, in uci.
+pub async fn eval_move_line(pos: &Chess, m: &Move, engine: &Arc<UciEngine>) -> (i32, Vec<String>) {
     let fen = Fen::from_setup(pos);
     let uci_move = Uci::from_move(m, CastlingMode::Standard);
     let analysis_job = GoJob::new()
         .pos_fen(fen)
         .pos_moves(uci_move.to_string())
-        .go_opt("nodes", 1000 * 1000);
+        .go_opt("nodes", ENGINE_NODES);
+
+    let result = engine.go(analysis_job).await.unwrap();
+    (centipawns(result.ai.score), engine_line(result.ai))
+}
+
+/// Best score and line from the position for the side to move, with the score of the
+/// second best move from the same MultiPV search. None when there is only one legal move.
+pub(crate) async fn search_best_two(
+    pos: &Chess,
+    engine: &Arc<UciEngine>,
+) -> (i32, Vec<String>, Option<i32>) {
+    //The result only keeps the last info line, so both lines are read from the info stream
+    let mut infos = engine.atx.subscribe();
+    let analysis_job = GoJob::new()
+        .uci_opt("MultiPV", 2)
+        .pos_fen(Fen::from_setup(pos))
+        .go_opt("nodes", ENGINE_NODES);
 
     let result = engine.go(analysis_job).await.unwrap();
-    match result.ai.score {
+    let mut lines: [Option<AnalysisInfo>; 2] = [None, None];
+    loop {
+        match infos.try_recv() {
+            Ok(info) if (1..=2).contains(&info.multipv) => lines[info.multipv - 1] = Some(info),
+            Ok(_) | Err(TryRecvError::Lagged(_)) => continue,
+            Err(_) => break,
+        }
+    }
+
+    let best = lines[0].unwrap_or(result.ai);
+    let second = lines[1].map(|info| centipawns(info.score));
+    (centipawns(best.score), engine_line(best), second)
+}
+
+pub(crate) fn centipawns(score: Score) -> i32 {
+    //Mates rank above every centipawn score, sooner mates higher, being mated the other way round
+    match score {
         Score::Cp(value) => value,
-        Score::Mate(mvs_mate) => 100_000 - mvs_mate,
+        Score::Mate(mvs_mate) if mvs_mate > 0 => 100_000 - mvs_mate,
+        Score::Mate(mvs_mate) => -100_000 - mvs_mate,
     }
 }
 
-fn group_blunders_by_phase(
-    blunders: &Vec<usize>,
-    middle_game: Option<i32>,
-    end_game: Option<i32>,
-) -> Blunders { 
-    let mut grouped_blunders = Blunders::empty();
-
-    for idx in blunders {
-        if Some(*idx as i32) < middle_game {
-            grouped_blunders.opening.push(*idx as i32);
-        } else if Some(*idx as i32) >= middle_game && Some(*idx as i32) < end_game {
-            grouped_blunders.middle_game.push(*idx as i32);
-        } else {
-            grouped_blunders.end_game.push(*idx as i32);
-        }
+fn engine_line(info: AnalysisInfo) -> Vec<String> {
+    info.pv()
+        .map(|pv| pv.split_whitespace().map(str::to_string).collect())
+        .unwrap_or_default()
+}
+
+pub fn speed_from_time_control(time_control: &str) -> Option<String> {
+    //Same buckets as lichess, estimated duration is base + 40 * increment seconds
+    if time_control == "-" {
+        return Some("correspondence".to_string());
     }
 
-    grouped_blunders
+    let mut parts = time_control.split('+');
+    let base = parts.next()?.parse::<u32>().ok()?;
+    let increment = parts.next().unwrap_or("0").parse::<u32>().ok()?;
+    let estimated = base + 40 * increment;
+
+    let speed = if estimated < 30 {
+        "ultrabullet"
+    } else if estimated < 180 {
+        "bullet"
+    } else if estimated < 480 {
+        "blitz"
+    } else if estimated < 1500 {
+        "rapid"
+    } else {
+        "classical"
+    };
+
+    Some(speed.to_string())
 }
 
 pub struct GameAnalyser {
     engine: Arc<UciEngine>,
+    classifier: Arc<OpeningClassifier>,
     success: bool,
     pos: Chess,
+    start: Chess, //Position the moves are played from, set by the FEN header
     pub game: Game,
     move_counter: usize,
     last_score: i32,
-    blunders: Vec<usize>,
+    blunders: Vec<i32>,
+    motifs: BTreeMap<i32, Vec<String>>,
+    last_line: Vec<String>, //Engine line for the side to move, from the previous evaluation
+    date: Option<NaiveDate>,
+    time: Option<NaiveTime>,
 }
 
 impl GameAnalyser {
-    pub async fn new() -> Self {
+    pub async fn new(classifier: Arc<OpeningClassifier>) -> Self {
         Self {
             engine: get_engine().await,
+            classifier,
             success: true,
             pos: Chess::default(),
+            start: Chess::default(),
             game: Game::empty(),
             move_counter: 0,
             last_score: 0,
             blunders: Vec::new(),
+            motifs: BTreeMap::new(),
+            last_line: Vec::new(),
+            date: None,
+            time: None,
         }
     }
 
-    fn is_end_game(&mut self) {
-        let board = self.pos.board();
-        let kings = board.kings();
-        let pawns = board.pawns();
-        let pieces = board.occupied();
-        let num_minor_major = (pieces & !kings & !pawns).count();
-
-        if num_minor_major <= 6 {
-            self.game.end_game = Some(self.move_counter as i32);
+    fn classify_opening(&mut self) {
+        //Called after the move at move_counter has been played
+        if self.move_counter >= self.classifier.max_ply() {
+            return;
         }
-    }
-
-    fn is_middle_game(&mut self) {
-        let board = self.pos.board();
-        let kings = board.kings();
-        let pawns = board.pawns();
-        let pieces = board.occupied();
 
-        let num_minor_major = (pieces & !kings & !pawns).count();
-        let white_backrank_count = (pieces & Bitboard::from_rank(Rank::First)).count();
-        let black_backrank_count = (pieces & Bitboard::from_rank(Rank::Eighth)).count();
-
-        if num_minor_major <= 10 || white_backrank_count < 4 || black_backrank_count < 4 {
-            self.game.middle_game = Some(self.move_counter as i32);
+        if let Some(opening) = self.classifier.lookup(&self.pos) {
+            self.game.book_opening_id = Some(opening.id);
+            self.game.left_book_ply = Some(self.move_counter as i32 + 1);
         }
     }
 }
@@ -111,9 +174,14 @@ impl AsyncVisitor for GameAnalyser {
         self.success = true;
         self.pos = Chess::default();
         self.game = Game::empty();
+        self.game.analysis = current_profile();
         self.move_counter = 0;
         self.last_score = 0;
         self.blunders = Vec::new();
+        self.motifs = BTreeMap::new();
+        self.last_line = Vec::new();
+        self.date = None;
+        self.time = None;
     }
 
     fn header(&mut self, key: &[u8], value: RawHeader<'_>) {
@@ -168,6 +236,26 @@ impl AsyncVisitor for GameAnalyser {
                     self.game.opening_id = Some(opening.to_string());
                 }
             }
+            b"UTCDate" | b"Date" => {
+                if let Ok(date) = std::str::from_utf8(value.as_bytes()) {
+                    if let Ok(parsed) = NaiveDate::parse_from_str(date, "%Y.%m.%d") {
+                        self.date = Some(parsed);
+                    }
+                }
+            }
+            b"UTCTime" => {
+                if let Ok(time) = std::str::from_utf8(value.as_bytes()) {
+                    if let Ok(parsed) = NaiveTime::parse_from_str(time, "%H:%M:%S") {
+                        self.time = Some(parsed);
+                    }
+                }
+            }
+            b"TimeControl" => {
+                if let Ok(time_control) = std::str::from_utf8(value.as_bytes()) {
+                    self.game.time_control = Some(time_control.to_string());
+                    self.game.speed = speed_from_time_control(time_control);
+                }
+            }
             b"Result" => {
                 if let Ok(result_string) = std::str::from_utf8(value.as_bytes()) {
                     self.game.winner = match result_string {
@@ -182,6 +270,11 @@ impl AsyncVisitor for GameAnalyser {
     }
 
     fn end_headers(&mut self) -> Skip {
+        self.start = self.pos.clone();
+        self.game.played_at = self.date.and_then(|date| match self.time {
+            Some(time) => Some(date.and_time(time)),
+            None => date.and_hms_opt(0, 0, 0),
+        });
         Skip(!self.success)
     }
 
@@ -195,14 +288,10 @@ impl AsyncVisitor for GameAnalyser {
                 Ok(m) => {
                     let uci = m.to_uci(self.pos.castles().mode()).to_string();
                     self.game.moves.push(uci);
-                    let score = eval_move(&self.pos, &m, &self.engine).await;
+                    let (score, line) = eval_move_line(&self.pos, &m, &self.engine).await;
+                    let before = self.pos.clone();
                     self.pos.play_unchecked(&m);
-
-                    if self.game.middle_game.is_none() {
-                        self.is_middle_game()
-                    } else if self.game.end_game.is_none() {
-                        self.is_end_game();
-                    }
+                    self.classify_opening();
 
                     if self.last_score != 0 {
                         let score_diff = self.last_score - score;
@@ -211,11 +300,20 @@ impl AsyncVisitor for GameAnalyser {
                             && (relative_score > 2.3 && score_diff.abs() > 150
                                 || relative_score < 0.5 && score_diff.abs() > 80)
                         {
-                            self.blunders.push(self.move_counter);
+                            self.blunders.push(self.move_counter as i32);
+                            let motifs = classify_blunder(&before, &m, &self.last_line, &line);
+                            self.motifs.insert(
+                                self.move_counter as i32,
+                                motifs
+                                    .iter()
+                                    .map(|motif| motif.as_str().to_string())
+                                    .collect(),
+                            );
                         }
                     }
 
                     self.last_score = score;
+                    self.last_line = line;
                     self.game.scores.push(score.to_string());
                     self.move_counter += 1;
                 }
@@ -227,10 +325,19 @@ impl AsyncVisitor for GameAnalyser {
     }
 
     async fn end_game(&mut self) -> Self::Result {
-        let grouped =
-            group_blunders_by_phase(&self.blunders, self.game.middle_game, self.game.end_game);
+        //The moves were played from the start position, so they always replay
+        let division =
+            divide(&self.start, &self.game.moves, &PhaseOptions::default()).unwrap_or_default();
+        self.game.middle_game = division.middle_game;
+        self.game.end_game = division.end_game;
+        let mut grouped = group_blunders(&self.blunders, &division);
+        grouped.motifs = std::mem::take(&mut self.motifs);
         println!("Blunders at {:?}", grouped);
         self.game.blunders = grouped;
+        if self.game.left_book_ply.is_none() {
+            self.game.left_book_ply = Some(0);
+        }
+        mark_book_exit(&mut self.game);
         false
     }
 }
@@ -1,4 +1,8 @@
+use hubble_db::models::blunder::Blunder;
 use hubble_db::models::game::Game;
+use hubble_db::models::game_filter::{GameFilter, SortField};
+use hubble_db::{Database, DbError};
+use shakmaty::{san::San, uci::Uci, CastlingMode, Chess, Position, Role};
 
 pub fn find_blunder(game: &Game) -> Vec<(usize, String)> {
     let mut prev_score = 0.;
@@ -18,3 +22,97 @@ pub fn find_blunder(game: &Game) -> Vec<(usize, String)> {
 
     blunders
 }
+
+fn role_name(role: Role) -> &'static str {
+    match role {
+        Role::Pawn => "pawn",
+        Role::Knight => "knight",
+        Role::Bishop => "bishop",
+        Role::Rook => "rook",
+        Role::Queen => "queen",
+        Role::King => "king",
+    }
+}
+
+/// One row per stored blunder with the move and the piece that made it,
+/// found by replaying the game's moves.
+pub fn blunder_rows(game: &Game) -> Vec<Blunder> {
+    let mut plies = game
+        .blunders
+        .opening
+        .iter()
+        .chain(&game.blunders.middle_game)
+        .chain(&game.blunders.end_game)
+        .copied()
+        .collect::<Vec<_>>();
+    plies.sort_unstable();
+
+    let mut rows = Vec::new();
+    let mut pos = Chess::default();
+    for (ply, uci) in game.moves.iter().enumerate() {
+        let m = match uci
+            .parse::<Uci>()
+            .ok()
+            .and_then(|uci| uci.to_move(&pos).ok())
+        {
+            Some(m) => m,
+            None => break,
+        };
+        if plies.binary_search(&(ply as i32)).is_ok() {
+            rows.push(Blunder {
+                game_id: game.id.clone(),
+                ply: ply as i32,
+                uci: m.to_uci(CastlingMode::Standard).to_string(),
+                san: San::from_move(&pos, &m).to_string(),
+                piece: role_name(m.role()).to_string(),
+                motifs: game
+                    .blunders
+                    .motifs
+                    .get(&(ply as i32))
+                    .cloned()
+                    .unwrap_or_default(),
+            });
+        }
+        pos.play_unchecked(&m);
+    }
+
+    rows
+}
+
+/// Stores the blunders of the games, replacing what was stored for them before.
+pub async fn index_blunders(db: &Database, games: &[Game]) -> Result<usize, DbError> {
+    let ids = games.iter().map(|game| game.id.clone()).collect();
+    let rows = games.iter().flat_map(blunder_rows).collect();
+    db.replace_blunders(ids, rows).await
+}
+
+/// Indexes the blunders of already stored games, a batch at a time. No engine is needed,
+/// the blunders found by the last analysis are kept.
+pub async fn index_stored_blunders(
+    db: &Database,
+    player: Option<&str>,
+    batch_size: i64,
+) -> Result<usize, DbError> {
+    let mut indexed = 0;
+    let mut offset = 0;
+
+    loop {
+        let filter = GameFilter {
+            player: player.map(str::to_string),
+            sort: SortField::Id,
+            descending: false,
+            limit: batch_size,
+            offset,
+            ..GameFilter::default()
+        };
+        let page = db.find_games(filter).await?;
+        if page.games.is_empty() {
+            break;
+        }
+
+        indexed += index_blunders(db, &page.games).await?;
+        offset += page.games.len() as i64;
+    }
+
+    Ok(indexed)
+}
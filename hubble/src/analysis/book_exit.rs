@@ -0,0 +1,73 @@
+use crate::analysis::eval::{clamp_score, white_scores};
+use hubble_db::models::game::Game;
+use shakmaty::{uci::Uci, Chess, Position};
+
+// Number of plies after leaving book used to measure the eval swing
+pub const BOOK_EXIT_WINDOW: usize = 6;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BookExit {
+    pub ply: usize,
+    pub by_white: bool,
+    pub swing: i32, //From the point of view of the side that left book
+}
+
+// Stored games don't keep their start position, the moves of a game from a custom one
+// don't replay from the standard start
+fn replays_from_start(game: &Game) -> bool {
+    let mut pos = Chess::default();
+    game.moves.iter().all(|uci| {
+        match uci
+            .parse::<Uci>()
+            .ok()
+            .and_then(|uci| uci.to_move(&pos).ok())
+        {
+            Some(m) => {
+                pos.play_unchecked(&m);
+                true
+            }
+            None => false,
+        }
+    })
+}
+
+/// Where the game left book, none when it never did. Games from a custom position never were
+/// in book, even plies are only white's moves from the standard start.
+pub fn book_exit(game: &Game, window: usize) -> Option<BookExit> {
+    if !replays_from_start(game) {
+        return None;
+    }
+    let ply = game.left_book_ply? as usize;
+    let scores = white_scores(game);
+    if ply >= scores.len() {
+        return None; //Never left book
+    }
+
+    let before = if ply == 0 { 0 } else { clamp_score(scores[ply - 1]) };
+    let after = clamp_score(scores[(ply + window).min(scores.len() - 1)]);
+    let by_white = ply % 2 == 0;
+
+    Some(BookExit {
+        ply,
+        by_white,
+        swing: if by_white {
+            after - before
+        } else {
+            before - after
+        },
+    })
+}
+
+pub fn mark_book_exit(game: &mut Game) {
+    match book_exit(game, BOOK_EXIT_WINDOW) {
+        Some(exit) => {
+            let side = if exit.by_white { "white" } else { "black" };
+            game.left_book_by = Some(side.to_string());
+            game.book_exit_swing = Some(exit.swing);
+        }
+        None => {
+            game.left_book_by = None;
+            game.book_exit_swing = None;
+        }
+    }
+}
@@ -0,0 +1,24 @@
+use hubble_db::models::game::Game;
+
+// Mate scores are stored as 100_000 - moves to mate, clamp them so averages stay meaningful
+pub const SCORE_CLAMP: i32 = 1000;
+
+pub fn clamp_score(score: i32) -> i32 {
+    score.clamp(-SCORE_CLAMP, SCORE_CLAMP)
+}
+
+pub fn white_scores(game: &Game) -> Vec<i32> {
+    //Engine scores are from the side to move after each ply, turn them into white's point of view
+    game.scores
+        .iter()
+        .enumerate()
+        .map(|(idx, sc)| {
+            let score = sc.parse::<i32>().unwrap_or(0);
+            if idx % 2 == 0 {
+                -score
+            } else {
+                score
+            }
+        })
+        .collect()
+}
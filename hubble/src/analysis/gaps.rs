@@ -0,0 +1,306 @@
+use crate::analysis::opening_tree::OpeningTree;
+use crate::analysis::repertoire::{player_repertoire, Repertoire, RepertoireMove};
+use hubble_db::models::game_filter::{Color, GameFilter};
+use hubble_db::{Database, DbError};
+use serde::Serialize;
+use shakmaty::{fen, san::San, uci::Uci, Chess, Position, Setup};
+use std::collections::{BTreeMap, HashMap};
+
+#[derive(Debug, Serialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum GapReason {
+    NeverFaced,
+    FewGames,
+    LowScore,
+    EvalDrop,
+}
+
+#[derive(Debug, Serialize)]
+pub struct RepertoireGap {
+    pub line: Vec<String>, //San moves leading to the opponent reply
+    pub reply: String,
+    pub reference_games: u32,
+    pub frequency: f64, //Chance of facing the reply, with the player choosing moves as often as they did
+    pub player_games: u32,
+    pub score: Option<f64>,
+    pub usual_response: Option<String>,
+    pub eval_drop: Option<f64>, //Centipawns lost by the usual response, from the player's point of view
+    pub reasons: Vec<GapReason>,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct GapOptions {
+    pub max_ply: usize,
+    pub min_games: u32,       //Fewer games than this against a reply is a gap
+    pub min_score: f64,       //Scoring below this against a reply is a gap
+    pub max_eval_drop: f64,   //Usual response losing more centipawns than this is a gap
+    pub min_frequency: f64,   //Replies rarer than this are not worth preparing
+    pub reference_games: i64, //Latest games of other players the replies are taken from
+}
+
+impl Default for GapOptions {
+    fn default() -> Self {
+        Self {
+            max_ply: 16,
+            min_games: 3,
+            min_score: 0.4,
+            max_eval_drop: 100.,
+            min_frequency: 0.02,
+            reference_games: 10_000,
+        }
+    }
+}
+
+fn score(mv: &RepertoireMove) -> f64 {
+    (mv.won as f64 + mv.drawn as f64 / 2.) / mv.games as f64
+}
+
+fn play(pos: &Chess, uci: &str) -> Option<(San, Chess)> {
+    let m = uci.parse::<Uci>().ok()?.to_move(pos).ok()?;
+    let mut next = pos.clone();
+    next.play_unchecked(&m);
+    Some((San::from_move(pos, &m), next))
+}
+
+// A position reached by following the player's moves and the reference replies
+struct Node {
+    pos: Chess,
+    line: Vec<String>, //The most likely way to reach it
+    frequency: f64,    //Chance of reaching it, summed over every way to get there
+    line_frequency: f64,
+}
+
+// Adds one more way of reaching the position, the line is kept from the most likely one
+fn reach(layer: &mut BTreeMap<String, Node>, pos: Chess, line: Vec<String>, frequency: f64) {
+    let node = layer.entry(fen::epd(&pos)).or_insert_with(|| Node {
+        pos,
+        line: Vec::new(),
+        frequency: 0.,
+        line_frequency: 0.,
+    });
+    node.frequency += frequency;
+    if frequency > node.line_frequency {
+        node.line = line;
+        node.line_frequency = frequency;
+    }
+}
+
+struct GapFinder<'a> {
+    repertoire: &'a Repertoire,
+    reference: &'a OpeningTree,
+    options: GapOptions,
+    gaps: HashMap<(String, String), (RepertoireGap, f64)>, //By position and reply uci
+}
+
+impl<'a> GapFinder<'a> {
+    fn player_to_move(&self, pos: &Chess) -> bool {
+        pos.turn().is_white() == (self.repertoire.color == Color::White)
+    }
+
+    fn expand(&mut self, node: &Node, next_layer: &mut BTreeMap<String, Node>) {
+        let epd = fen::epd(&node.pos);
+        let with = |san: San| {
+            let mut line = node.line.clone();
+            line.push(san.to_string());
+            line
+        };
+
+        if self.player_to_move(&node.pos) {
+            //Follow every move the player has played here, as often as they played it
+            let moves = match self.repertoire.positions.get(&epd) {
+                Some(moves) => moves,
+                None => return,
+            };
+            let total = moves.iter().map(|mv| mv.games).sum::<u32>();
+            for mv in moves {
+                if let Some((san, next)) = play(&node.pos, &mv.uci) {
+                    let frequency = node.frequency * mv.games as f64 / total as f64;
+                    reach(next_layer, next, with(san), frequency);
+                }
+            }
+            return;
+        }
+
+        let (reference, repertoire) = (self.reference, self.repertoire);
+        let replies = reference.moves(&node.pos);
+        let total = replies.iter().map(|entry| entry.n).sum::<u32>();
+        let faced = repertoire.positions.get(&epd);
+
+        for entry in replies {
+            let reply_frequency = node.frequency * entry.n as f64 / total as f64;
+            let uci = entry.uci().to_string();
+            let (san, next) = match play(&node.pos, &uci) {
+                Some(played) => played,
+                None => continue,
+            };
+            let reply = faced.and_then(|moves| moves.iter().find(|mv| mv.uci == uci));
+
+            let key = (epd.clone(), uci);
+            match self.gaps.get_mut(&key) {
+                Some((gap, line_frequency)) => {
+                    gap.frequency += reply_frequency;
+                    if reply_frequency > *line_frequency {
+                        gap.line = node.line.clone();
+                        *line_frequency = reply_frequency;
+                    }
+                }
+                None => {
+                    let gap = self.check_reply(&node.line, san.to_string(), &next, entry.n, reply);
+                    if let Some(mut gap) = gap {
+                        gap.frequency = reply_frequency;
+                        self.gaps.insert(key, (gap, reply_frequency));
+                    }
+                }
+            }
+
+            if reply.is_some() {
+                reach(next_layer, next, with(san), reply_frequency);
+            }
+        }
+    }
+
+    //The gap the reply is for the player, its frequency is filled in by the caller
+    fn check_reply(
+        &self,
+        line: &[String],
+        san: String,
+        next: &Chess,
+        reference_games: u32,
+        reply: Option<&RepertoireMove>,
+    ) -> Option<RepertoireGap> {
+        let mut gap = RepertoireGap {
+            line: line.to_vec(),
+            reply: san,
+            reference_games,
+            frequency: 0.,
+            player_games: 0,
+            score: None,
+            usual_response: None,
+            eval_drop: None,
+            reasons: Vec::new(),
+        };
+
+        let reply = match reply {
+            Some(reply) => reply,
+            None => {
+                gap.reasons.push(GapReason::NeverFaced);
+                return Some(gap);
+            }
+        };
+
+        gap.player_games = reply.games;
+        gap.score = Some(score(reply));
+        if reply.games < self.options.min_games {
+            gap.reasons.push(GapReason::FewGames);
+        } else if score(reply) < self.options.min_score {
+            gap.reasons.push(GapReason::LowScore);
+        }
+
+        //Responses are sorted by games, the first one is the usual response
+        let response = self
+            .repertoire
+            .positions
+            .get(&fen::epd(next))
+            .and_then(|moves| moves.first());
+        if let Some(response) = response {
+            gap.usual_response = Some(response.san.clone());
+            if let (Some(before), Some(after)) = (reply.avg_eval, response.avg_eval) {
+                let drop = before - after;
+                gap.eval_drop = Some(drop);
+                if drop > self.options.max_eval_drop {
+                    gap.reasons.push(GapReason::EvalDrop);
+                }
+            }
+        }
+
+        if gap.reasons.is_empty() {
+            None
+        } else {
+            Some(gap)
+        }
+    }
+}
+
+/// Opponent replies, common in the reference games, that the player has never faced,
+/// rarely faced, scores badly against or answers with a move that drops the eval.
+/// Most likely replies first, the chance of a reply adds up over every line that reaches it.
+pub fn find_gaps(
+    repertoire: &Repertoire,
+    reference: &OpeningTree,
+    options: GapOptions,
+) -> Vec<RepertoireGap> {
+    let mut finder = GapFinder {
+        repertoire,
+        reference,
+        options,
+        gaps: HashMap::new(),
+    };
+
+    //One ply at a time, so every way into a position is counted before it is expanded
+    let mut layer = BTreeMap::new();
+    reach(&mut layer, Chess::default(), Vec::new(), 1.);
+    for _ in 0..options.max_ply {
+        let mut next_layer = BTreeMap::new();
+        for node in layer.values() {
+            if node.frequency >= options.min_frequency {
+                finder.expand(node, &mut next_layer);
+            }
+        }
+        layer = next_layer;
+    }
+
+    let mut gaps = finder
+        .gaps
+        .into_values()
+        .map(|(gap, _)| gap)
+        .filter(|gap| gap.frequency >= options.min_frequency)
+        .collect::<Vec<_>>();
+    gaps.sort_by(|a, b| {
+        b.frequency
+            .total_cmp(&a.frequency)
+            .then_with(|| a.line.cmp(&b.line))
+            .then_with(|| a.reply.cmp(&b.reply))
+    });
+    gaps
+}
+
+pub async fn reference_tree(
+    db: &Database,
+    exclude_player: &str,
+    max_ply: usize,
+    max_games: i64,
+) -> Result<OpeningTree, DbError> {
+    //Stored games except the player's own, left out before the limit so they don't use it up
+    let filter = GameFilter {
+        exclude_player: Some(exclude_player.to_string()),
+        limit: max_games,
+        ..GameFilter::default()
+    };
+    let page = db.find_games(filter).await?;
+
+    let mut tree = OpeningTree::with_max_ply(max_ply);
+    for game in &page.games {
+        tree.add_game(game);
+    }
+
+    Ok(tree)
+}
+
+pub async fn player_gaps(
+    db: &Database,
+    player: &str,
+    color: Color,
+    reference: Option<&OpeningTree>,
+    options: GapOptions,
+) -> Result<Vec<RepertoireGap>, DbError> {
+    let repertoire = player_repertoire(db, player, color, options.max_ply, 1000).await?;
+
+    match reference {
+        Some(reference) => Ok(find_gaps(&repertoire, reference, options)),
+        None => {
+            let reference =
+                reference_tree(db, player, options.max_ply, options.reference_games).await?;
+            Ok(find_gaps(&repertoire, &reference, options))
+        }
+    }
+}
@@ -1,9 +1,23 @@
 mod analyser;
 pub mod blunder;
+pub mod book_exit;
+pub mod eval;
+pub mod gaps;
+pub mod motif;
 pub mod opening;
+mod opening_classifier;
 mod opening_counter;
 pub mod opening_tree;
+pub mod phase;
+pub mod preparation;
+pub mod puzzle;
+pub mod repertoire;
+pub mod stats;
 
-pub use analyser::GameAnalyser;
+pub use analyser::{
+    current_profile, engine_available, GameAnalyser, ANALYSIS_VERSION, ENGINE_NAME, ENGINE_NODES,
+    ENGINE_PATH,
+};
 pub use opening::best_opening;
-pub use opening_counter::{OpeningCounter, OpeningResult};
+pub use opening_classifier::{replay_san_line, Classification, OpeningClassifier};
+pub use opening_counter::{group_openings, OpeningCounter, OpeningResult};
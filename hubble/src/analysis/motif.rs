@@ -0,0 +1,204 @@
+use serde::{Deserialize, Serialize};
+use shakmaty::{attacks, uci::Uci, Bitboard, Chess, Color, Move, Position, Role, Setup, Square};
+use std::str::FromStr;
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[serde(rename_all = "snake_case")]
+pub enum Motif {
+    HangingPiece, //The refutation takes a piece left undefended or defended by less
+    AllowedFork,
+    MissedFork,
+    Pin,
+    BackRank,
+    MissedMate,
+    AllowedMate,
+}
+
+impl Motif {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Motif::HangingPiece => "hanging_piece",
+            Motif::AllowedFork => "allowed_fork",
+            Motif::MissedFork => "missed_fork",
+            Motif::Pin => "pin",
+            Motif::BackRank => "back_rank",
+            Motif::MissedMate => "missed_mate",
+            Motif::AllowedMate => "allowed_mate",
+        }
+    }
+}
+
+impl FromStr for Motif {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "hanging_piece" => Ok(Motif::HangingPiece),
+            "allowed_fork" => Ok(Motif::AllowedFork),
+            "missed_fork" => Ok(Motif::MissedFork),
+            "pin" => Ok(Motif::Pin),
+            "back_rank" => Ok(Motif::BackRank),
+            "missed_mate" => Ok(Motif::MissedMate),
+            "allowed_mate" => Ok(Motif::AllowedMate),
+            _ => Err(()),
+        }
+    }
+}
+
+fn value(role: Role) -> u32 {
+    match role {
+        Role::Pawn => 1,
+        Role::Knight | Role::Bishop => 3,
+        Role::Rook => 5,
+        Role::Queen => 9,
+        Role::King => 100,
+    }
+}
+
+/// Positions along an engine line, each one after a move of the line.
+/// Stops at the first move that is not legal.
+fn replay(start: &Chess, line: &[String]) -> Vec<(Move, Chess)> {
+    let mut pos = start.clone();
+    let mut played = Vec::new();
+    for uci in line {
+        let m = match uci
+            .parse::<Uci>()
+            .ok()
+            .and_then(|uci| uci.to_move(&pos).ok())
+        {
+            Some(m) => m,
+            None => break,
+        };
+        pos.play_unchecked(&m);
+        played.push((m, pos.clone()));
+    }
+    played
+}
+
+fn is_defended(pos: &Chess, square: Square, color: Color) -> bool {
+    let board = pos.board();
+    board.attacks_to(square, color, board.occupied()).any()
+}
+
+/// The piece on the square attacks two enemy pieces it wins material against,
+/// the king, something worth more than itself or something undefended.
+fn is_fork(pos: &Chess, square: Square) -> bool {
+    let board = pos.board();
+    let forker = match board.piece_at(square) {
+        Some(piece) => piece,
+        None => return false,
+    };
+
+    let targets = board.attacks_from(square) & board.by_color(!forker.color);
+    targets
+        .into_iter()
+        .filter(|&target| match board.role_at(target) {
+            Some(role) => {
+                role == Role::King
+                    || value(role) > value(forker.role)
+                    || !is_defended(pos, target, !forker.color)
+            }
+            None => false,
+        })
+        .count()
+        >= 2
+}
+
+/// The slider on the square pins an enemy piece to a more valuable one behind it.
+fn is_pin(pos: &Chess, square: Square) -> bool {
+    let board = pos.board();
+    let pinner = match board.piece_at(square) {
+        Some(piece) if matches!(piece.role, Role::Bishop | Role::Rook | Role::Queen) => piece,
+        _ => return false,
+    };
+    let lines = match pinner.role {
+        Role::Bishop => attacks::bishop_attacks(square, Bitboard::EMPTY),
+        Role::Rook => attacks::rook_attacks(square, Bitboard::EMPTY),
+        _ => attacks::queen_attacks(square, Bitboard::EMPTY),
+    };
+
+    let theirs = board.by_color(!pinner.color);
+    (lines & theirs).into_iter().any(|behind| {
+        let pinned = match (attacks::between(square, behind) & board.occupied()).single_square() {
+            Some(pinned) if theirs.contains(pinned) => pinned,
+            _ => return false,
+        };
+        match (board.role_at(pinned), board.role_at(behind)) {
+            (Some(pinned), Some(target)) => value(target) > value(pinned),
+            _ => false,
+        }
+    })
+}
+
+fn is_back_rank_mate(pos: &Chess) -> bool {
+    let board = pos.board();
+    let mated = pos.turn();
+    let king = match board.king_of(mated) {
+        Some(king) => king,
+        None => return false,
+    };
+    king.rank() == mated.backrank()
+        && pos.checkers().into_iter().any(|checker| {
+            checker.rank() == king.rank()
+                && matches!(board.role_at(checker), Some(Role::Rook | Role::Queen))
+        })
+}
+
+/// Tags a blunder with the tactics behind it. `best_line` is the engine's line from the
+/// position before the blunder, `refutation` its line after the blunder was played.
+pub fn classify_blunder(
+    before: &Chess,
+    played: &Move,
+    best_line: &[String],
+    refutation: &[String],
+) -> Vec<Motif> {
+    let player = before.turn();
+    let mut after = before.clone();
+    after.play_unchecked(played);
+    let mut motifs = Vec::new();
+
+    let best = replay(before, best_line);
+    match (best.first(), best.last()) {
+        (_, Some((_, end))) if end.is_checkmate() && end.turn() != player => {
+            motifs.push(Motif::MissedMate);
+        }
+        (Some((m, next)), _) if m != played && is_fork(next, m.to()) => {
+            motifs.push(Motif::MissedFork);
+        }
+        _ => {}
+    }
+
+    let refuted = replay(&after, refutation);
+    match refuted.last() {
+        Some((_, end)) if end.is_checkmate() && end.turn() == player => {
+            motifs.push(Motif::AllowedMate);
+            if is_back_rank_mate(end) {
+                motifs.push(Motif::BackRank);
+            }
+        }
+        _ => {
+            if let Some((m, next)) = refuted.first() {
+                if let Some(captured) = m.capture() {
+                    if !is_defended(&after, m.to(), player) || value(captured) > value(m.role()) {
+                        motifs.push(Motif::HangingPiece);
+                    }
+                }
+                if is_pin(next, m.to()) {
+                    motifs.push(Motif::Pin);
+                }
+            }
+            //The fork can come right away or after a forcing move
+            if refuted
+                .iter()
+                .step_by(2)
+                .take(2)
+                .any(|(m, next)| is_fork(next, m.to()))
+            {
+                motifs.push(Motif::AllowedFork);
+            }
+        }
+    }
+
+    motifs.sort();
+    motifs
+}
@@ -1,84 +1,75 @@
-use crate::analysis::{OpeningCounter, OpeningResult};
+use crate::analysis::book_exit::mark_book_exit;
+use crate::analysis::{group_openings, OpeningClassifier, OpeningCounter, OpeningResult};
 use crate::lichess::get_games_player;
 use anyhow::Result;
-use hubble_db::models::Opening;
-use hubble_db::PgConnection;
+use hubble_db::models::opening_name::OpeningLevel;
+use hubble_db::Database;
 use pgn_reader::BufferedReader;
-use shakmaty::{san::San, Chess, Position};
+use shakmaty::fen::{self, Fen};
+use shakmaty::{CastlingMode, Chess};
 
-pub fn match_length(opening: &Opening, moves: &[String]) -> usize {
-    //moves - vector with the moves in uci format
-    let cleaned = opening.pgn.replace(".", "");
-    let splits = cleaned.split(' ');
-    let mut length = 0;
-    let mut idx = 0;
-
-    let mut board = Chess::default();
-
-    for mv in splits {
-        if mv.len() == 1 {
-            continue;
-        }
-        if let Ok(san) = mv.parse::<San>() {
-            if let Ok(parsed_move) = san.to_move(&board) {
-                board.play_unchecked(&parsed_move);
-                println!("{} {}", moves[idx], parsed_move);
-                if moves[idx] == parsed_move.to_string().replace("-", "") {
-                    length += 1;
-                    idx += 1;
-                }
-            } else {
-                break;
-            }
-        } else {
-            break;
-        }
-    }
+pub async fn load_classifier(db: &Database) -> Result<OpeningClassifier> {
+    let openings = db.get_all_openings().await?;
+    Ok(OpeningClassifier::new(openings))
+}
 
-    length
+pub fn fen_to_epd(fen: &str) -> Option<String> {
+    //Normalised like the stored epds, the en passant square is only kept when the capture is legal
+    let pos: Chess = Fen::from_ascii(fen.as_bytes())
+        .ok()?
+        .position(CastlingMode::Chess960)
+        .ok()?;
+    Some(fen::epd(&pos))
 }
 
-pub fn match_length_sans(opening: &Opening, moves: &[String]) -> usize {
-    //For when both opening and moves is in san format
-    let cleaned = opening.pgn.replace(".", "");
-    let splits = cleaned.split(' ');
-    let mut length = 0;
-    let mut idx = 0;
+pub async fn classify_stored_games(
+    db: &Database,
+    classifier: &OpeningClassifier,
+    batch_size: i64,
+) -> Result<usize> {
+    //Fills in the book opening and exit for games saved before they were part of the analysis
+    let mut classified = 0;
+    let mut after = String::new();
 
-    for mv in splits {
-        if idx >= moves.len() {
+    loop {
+        let games = db.get_unclassified_games(after.clone(), batch_size).await?;
+        if games.is_empty() {
             break;
         }
 
-        if mv.parse::<u32>().is_ok() {
-            continue;
+        for mut game in games {
+            after = game.id.clone();
+            let (opening_id, ply) = match classifier.classify_uci(&game.moves) {
+                Some(classification) => (Some(classification.opening.id), classification.ply),
+                None => (None, 0),
+            };
+            game.book_opening_id = opening_id;
+            game.left_book_ply = Some(ply as i32);
+            mark_book_exit(&mut game);
+            db.set_game_opening(game).await?;
+            classified += 1;
         }
-
-        if moves[idx] == mv {
-            length += 1;
-        } else {
-            break;
-        }
-        idx += 1;
     }
-    length
+
+    Ok(classified)
 }
 
 pub async fn best_opening(
     player_id: &str,
-    conn: &PgConnection,
+    db: &Database,
     num: usize,
     white: Option<bool>,
+    level: OpeningLevel,
 ) -> Result<Vec<(String, OpeningResult)>> {
     //white - true if only to analyse games where white, false - black. None - both
     let pgn = get_games_player(player_id, num).await?;
-    let mut counter = OpeningCounter::new(conn, player_id.to_string(), white);
+    let classifier = load_classifier(db).await?;
+    let mut counter = OpeningCounter::new(&classifier, player_id.to_string(), white);
     let mut reader = BufferedReader::new_cursor(&pgn[..]);
 
-    while let Some(_ok) = reader.read_game(&mut counter).unwrap() {}
+    while let Some(_ok) = reader.read_game(&mut counter)? {}
 
-    Ok(counter
-        .openings
+    Ok(group_openings(&counter.openings, level)
         .into_iter()
         .collect::<Vec<(String, OpeningResult)>>())
 }
@@ -0,0 +1,127 @@
+use hubble_db::models::Opening;
+use shakmaty::{fen, san::SanPlus, uci::Uci, Chess, Position};
+use std::collections::HashMap;
+
+#[derive(Debug)]
+struct BookEntry {
+    opening: Opening,
+    ply: usize,
+}
+
+#[derive(Debug)]
+pub struct Classification<'a> {
+    pub opening: &'a Opening,
+    pub ply: usize, //Number of plies played when the deepest known position was reached
+}
+
+pub fn replay_san_line(pgn: &str) -> Option<Vec<Chess>> {
+    //Every position after each move of a line like "1. e4 e5 2. Nf3"
+    let mut pos = Chess::default();
+    let mut positions = Vec::new();
+
+    for token in pgn.replace('.', " ").split_whitespace() {
+        if token.parse::<u32>().is_ok() {
+            continue;
+        }
+
+        let san = token.parse::<SanPlus>().ok()?.san;
+        let m = san.to_move(&pos).ok()?;
+        pos.play_unchecked(&m);
+        positions.push(pos.clone());
+    }
+
+    Some(positions)
+}
+
+/// Classifies games by the deepest book position they reach, so transpositions and
+/// games without an ECO header are recognised.
+#[derive(Debug, Default)]
+pub struct OpeningClassifier {
+    entries: Vec<BookEntry>,
+    positions: HashMap<String, usize>,
+    max_ply: usize,
+}
+
+impl OpeningClassifier {
+    pub fn new(openings: Vec<Opening>) -> Self {
+        let mut classifier = Self::default();
+
+        for opening in openings {
+            //Openings imported with their final position skip replaying the san
+            let (epd, ply) = match (&opening.epd, opening.ply) {
+                (Some(epd), Some(ply)) if ply > 0 => (epd.clone(), ply as usize),
+                _ => match replay_san_line(&opening.pgn) {
+                    Some(positions) if !positions.is_empty() => {
+                        (fen::epd(&positions[positions.len() - 1]), positions.len())
+                    }
+                    _ => continue,
+                },
+            };
+
+            //Two lines ending in the same position keep the shortest one, then the lowest id,
+            //the same rule as find_opening_by_position
+            if let Some(&idx) = classifier.positions.get(&epd) {
+                let kept = &classifier.entries[idx];
+                if (kept.ply, kept.opening.id) <= (ply, opening.id) {
+                    continue;
+                }
+            }
+
+            classifier.max_ply = classifier.max_ply.max(ply);
+            classifier.positions.insert(epd, classifier.entries.len());
+            classifier.entries.push(BookEntry { opening, ply });
+        }
+
+        classifier
+    }
+
+    pub fn max_ply(&self) -> usize {
+        self.max_ply
+    }
+
+    pub fn lookup(&self, pos: &Chess) -> Option<&Opening> {
+        self.positions
+            .get(&fen::epd(pos))
+            .map(|&idx| &self.entries[idx].opening)
+    }
+
+    pub fn classify_positions<'a, I>(&'a self, positions: I) -> Option<Classification<'a>>
+    where
+        I: IntoIterator<Item = Chess>,
+    {
+        let mut deepest = None;
+
+        for (idx, pos) in positions.into_iter().take(self.max_ply).enumerate() {
+            if let Some(opening) = self.lookup(&pos) {
+                deepest = Some(Classification {
+                    opening,
+                    ply: idx + 1,
+                });
+            }
+        }
+
+        deepest
+    }
+
+    pub fn classify_uci(&self, moves: &[String]) -> Option<Classification<'_>> {
+        let mut pos = Chess::default();
+        let positions = moves.iter().map_while(|mv| {
+            let m = mv.parse::<Uci>().ok()?.to_move(&pos).ok()?;
+            pos.play_unchecked(&m);
+            Some(pos.clone())
+        });
+
+        self.classify_positions(positions)
+    }
+
+    pub fn classify_san(&self, moves: &[String]) -> Option<Classification<'_>> {
+        let mut pos = Chess::default();
+        let positions = moves.iter().map_while(|mv| {
+            let m = mv.parse::<SanPlus>().ok()?.san.to_move(&pos).ok()?;
+            pos.play_unchecked(&m);
+            Some(pos.clone())
+        });
+
+        self.classify_positions(positions)
+    }
+}
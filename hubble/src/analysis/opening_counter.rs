@@ -1,15 +1,18 @@
-use crate::analysis::opening::match_length_sans;
-use hubble_db::models::{get_all_openings, Opening};
-use hubble_db::PgConnection;
+use crate::analysis::stats::{expected_score, wilson_interval, Z_95};
+use crate::analysis::OpeningClassifier;
+use hubble_db::models::opening_name::{OpeningLevel, OpeningName};
 use pgn_reader::{RawHeader, SanPlus, Skip, Visitor};
+use shakmaty::{fen::Fen, CastlingMode, Chess, Position};
 use std::collections::HashMap;
 use std::fmt;
 
-#[derive(Debug, Copy, Clone)]
+#[derive(Debug, Copy, Clone, PartialEq)]
 pub struct OpeningResult {
     pub won: u32,
     pub tie: u32,
     pub lost: u32,
+    pub expected: f64, //Sum of the Elo expected scores of the rated games
+    pub rated: u32,    //Games where both ratings are known
 }
 
 impl OpeningResult {
@@ -18,8 +21,59 @@ impl OpeningResult {
             won: 0,
             tie: 0,
             lost: 0,
+            expected: 0.,
+            rated: 0,
         }
     }
+
+    pub fn total(&self) -> u32 {
+        self.won + self.tie + self.lost
+    }
+
+    pub fn add(&mut self, other: &OpeningResult) {
+        self.won += other.won;
+        self.tie += other.tie;
+        self.lost += other.lost;
+        self.expected += other.expected;
+        self.rated += other.rated;
+    }
+
+    /// Points per game, a draw is half a point.
+    pub fn score(&self) -> f64 {
+        match self.total() {
+            0 => 0.,
+            total => (self.won as f64 + self.tie as f64 / 2.) / total as f64,
+        }
+    }
+
+    /// 95% Wilson interval of the score, wide for openings with few games.
+    pub fn interval(&self) -> (f64, f64) {
+        wilson_interval(self.score(), self.total(), Z_95)
+    }
+
+    /// Average score the ratings predicted, none when no game was rated.
+    pub fn expected_score(&self) -> Option<f64> {
+        match self.rated {
+            0 => None,
+            rated => Some(self.expected / rated as f64),
+        }
+    }
+}
+
+/// Adds up the results of openings sharing a name at the given level,
+/// every Sicilian line counts towards "Sicilian Defense" at the family level.
+pub fn group_openings(
+    openings: &HashMap<String, OpeningResult>,
+    level: OpeningLevel,
+) -> HashMap<String, OpeningResult> {
+    let mut grouped = HashMap::new();
+    for (name, result) in openings {
+        grouped
+            .entry(OpeningName::parse(name).at(level))
+            .or_insert_with(OpeningResult::new)
+            .add(result);
+    }
+    grouped
 }
 
 impl fmt::Display for OpeningResult {
@@ -29,49 +83,59 @@ impl fmt::Display for OpeningResult {
 }
 
 #[derive(Debug)]
-pub struct OpeningCounter {
+pub struct OpeningCounter<'a> {
     pub openings: HashMap<String, OpeningResult>,
-    ecos: HashMap<String, Vec<Opening>>,
+    classifier: &'a OpeningClassifier,
     current_eco: String,
-    current_moves: Vec<String>,
-    is_white: bool,
+    current_opening: Option<String>,
+    pos: Chess,
+    ply: usize,
+    success: bool,
+    is_white: Option<bool>, //None when the player is not in the game
     result: String,
+    white_elo: Option<f64>,
+    black_elo: Option<f64>,
     player: String,
     white_only: Option<bool>, //true - only games where player is white is analysed, false - black, none - both
 }
 
-impl OpeningCounter {
-    pub fn new(conn: &PgConnection, player: String, white_only: Option<bool>) -> Self {
-        let mut ecos = HashMap::<String, Vec<Opening>>::new();
-        let openings = get_all_openings(conn);
-        for op in openings {
-            if let Some(o) = ecos.get_mut(&op.eco) {
-                o.push(op);
-            } else {
-                ecos.insert(op.eco.clone(), vec![op]);
-            }
-        }
-
+impl<'a> OpeningCounter<'a> {
+    pub fn new(
+        classifier: &'a OpeningClassifier,
+        player: String,
+        white_only: Option<bool>,
+    ) -> Self {
         Self {
             openings: HashMap::new(),
+            classifier,
             current_eco: String::from(""),
-            current_moves: Vec::new(),
-            ecos,
+            current_opening: None,
+            pos: Chess::default(),
+            ply: 0,
+            success: true,
             player,
-            is_white: true,
+            is_white: None,
             result: String::from(""),
+            white_elo: None,
+            black_elo: None,
             white_only,
         }
     }
 }
 
-impl Visitor for OpeningCounter {
+impl<'a> Visitor for OpeningCounter<'a> {
     type Result = bool;
 
     fn begin_game(&mut self) {
-        self.current_moves = Vec::new();
+        self.current_eco = String::from("");
+        self.current_opening = None;
+        self.pos = Chess::default();
+        self.ply = 0;
+        self.success = true;
         self.result = String::from("");
-        self.is_white = true;
+        self.white_elo = None;
+        self.black_elo = None;
+        self.is_white = None;
     }
 
     fn header(&mut self, key: &[u8], value: RawHeader<'_>) {
@@ -80,21 +144,39 @@ impl Visitor for OpeningCounter {
                 b"ECO" => {
                     self.current_eco = value_str.to_string();
                 }
-                b"White" => {
-                    self.is_white = value_str == self.player;
+                b"White" if value_str.eq_ignore_ascii_case(&self.player) => {
+                    self.is_white = Some(true);
+                }
+                b"Black" if value_str.eq_ignore_ascii_case(&self.player) => {
+                    self.is_white = Some(false);
                 }
                 b"Result" => {
                     self.result = value_str.to_string();
                 }
+                b"WhiteElo" => {
+                    self.white_elo = value_str.parse().ok(); //"?" when unknown
+                }
+                b"BlackElo" => {
+                    self.black_elo = value_str.parse().ok();
+                }
+                b"FEN" => match Fen::from_ascii(value.as_bytes()) {
+                    Ok(fen) => match fen.position(CastlingMode::Chess960) {
+                        Ok(pos) => self.pos = pos,
+                        Err(_) => self.success = false,
+                    },
+                    Err(_) => self.success = false,
+                },
                 _ => {}
             }
         }
     }
 
     fn end_headers(&mut self) -> Skip {
-        match self.white_only {
-            Some(val) => Skip(val == self.is_white),
-            None => Skip(false),
+        match (self.is_white, self.white_only) {
+            _ if !self.success => Skip(true),
+            (None, _) => Skip(true),
+            (Some(is_white), Some(white_only)) => Skip(is_white != white_only),
+            (Some(_), None) => Skip(false),
         }
     }
 
@@ -103,40 +185,71 @@ impl Visitor for OpeningCounter {
     }
 
     fn san(&mut self, san_plus: SanPlus) {
-        self.current_moves.push(san_plus.to_string());
+        if !self.success {
+            return;
+        }
+
+        if self.ply >= self.classifier.max_ply() {
+            //Past the deepest book position, only the move count matters
+            self.ply += 1;
+            return;
+        }
+
+        match san_plus.san.to_move(&self.pos) {
+            Ok(m) => {
+                self.pos.play_unchecked(&m);
+                self.ply += 1;
+                if let Some(opening) = self.classifier.lookup(&self.pos) {
+                    self.current_opening = Some(opening.name.clone());
+                }
+            }
+            Err(_) => self.success = false,
+        }
     }
 
     fn end_game(&mut self) -> Self::Result {
-        if self.current_moves.is_empty() {
+        let is_white = match self.is_white {
+            Some(is_white) => is_white,
+            None => return false,
+        };
+
+        if !self.success || self.ply == 0 {
             return false;
         }
 
-        if let Some(relevant_openings) = self.ecos.get(&self.current_eco) {
-            let mut longest = 0;
-            let mut opening_name = "";
-            for op in relevant_openings {
-                let length = match_length_sans(op, &self.current_moves);
-                if length > longest {
-                    longest = length;
-                    opening_name = &op.name;
-                }
-            }
+        let player_won = match self.result.as_str() {
+            "1-0" => Some(is_white),
+            "0-1" => Some(!is_white),
+            "1/2-1/2" => None,
+            _ => return false, //Unfinished game
+        };
 
-            if opening_name.is_empty() {
-                let opening_count = self
-                    .openings
-                    .entry(opening_name.to_string())
-                    .or_insert_with(OpeningResult::new);
-                let white_won = self.result == "1-0";
-                if self.result == "1/2-1/2" {
-                    opening_count.tie += 1;
-                } else if white_won == self.is_white {
-                    opening_count.won += 1;
-                } else {
-                    opening_count.lost += 1;
-                }
-            }
+        //Games that never reach a known position are counted under their ECO code
+        let opening_name = match self.current_opening.take() {
+            Some(name) => name,
+            None if !self.current_eco.is_empty() => self.current_eco.clone(),
+            None => return false,
+        };
+        let opening_count = self
+            .openings
+            .entry(opening_name)
+            .or_insert_with(OpeningResult::new);
+
+        match player_won {
+            Some(true) => opening_count.won += 1,
+            Some(false) => opening_count.lost += 1,
+            None => opening_count.tie += 1,
         }
-        false
+
+        if let (Some(white_elo), Some(black_elo)) = (self.white_elo, self.black_elo) {
+            opening_count.rated += 1;
+            opening_count.expected += if is_white {
+                expected_score(white_elo, black_elo)
+            } else {
+                expected_score(black_elo, white_elo)
+            };
+        }
+
+        true
     }
 }
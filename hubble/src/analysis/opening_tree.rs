@@ -1,82 +1,271 @@
-use std::collections::HashMap;
+use std::cmp::Reverse;
+use std::collections::{HashMap, HashSet};
 use std::fs::File;
-use std::io;
+use std::io::{self, Read};
 
 use shakmaty::fen::Fen;
-use shakmaty::{fen, uci::Uci, CastlingMode, Chess, Move, Position};
+use shakmaty::san::San;
+use shakmaty::zobrist::ZobristHash;
+use shakmaty::{
+    fen, uci::Uci, CastlingMode, Chess, Color, Move, Outcome, Position, Role, Setup, Square,
+};
 
+use crate::lichess::get_games_player;
+use anyhow::{anyhow, Result};
+use hubble_db::models::game::Game;
+use hubble_db::models::game_filter::GameFilter;
+use hubble_db::Database;
 use pgn_reader::{BufferedReader, RawHeader, SanPlus, Skip, Visitor};
 
+use serde::ser::{SerializeStruct, Serializer};
 use serde::Serialize;
 
-fn insert_move(moves: &mut Vec<MoveEntry>, mv: &str) {
-    let mut found = false;
+// Games read from a pgn between two prunes of the rare moves
+pub const PRUNE_INTERVAL: usize = 100_000;
 
-    for entry in moves.iter_mut() {
-        if entry.mv == mv {
-            entry.n += 1;
-            found = true;
-        }
+fn pack_uci(uci: &Uci) -> Option<u16> {
+    //from in bits 0-5, to in bits 6-11, promotion role in bits 12-14
+    match uci {
+        Uci::Normal {
+            from,
+            to,
+            promotion,
+        } => Some(*from as u16 | (*to as u16) << 6 | promotion.map_or(0, |role| role as u16) << 12),
+        _ => None,
     }
+}
 
-    if !found {
-        moves.push(MoveEntry {
-            mv: mv.to_string(),
-            n: 1,
-        });
+fn unpack_uci(packed: u16) -> Uci {
+    let role = (packed >> 12) as usize;
+    Uci::Normal {
+        from: Square::new(u32::from(packed & 0x3f)),
+        to: Square::new(u32::from((packed >> 6) & 0x3f)),
+        promotion: if role == 0 {
+            None
+        } else {
+            Some(Role::ALL[role - 1])
+        },
     }
 }
 
-#[allow(dead_code)]
-fn find_most_popular(moves: &[MoveEntry]) -> String {
-    let mut n_max = 0;
-    let mut pop = "e2e4";
+#[derive(Debug, Clone, Copy)]
+pub struct MoveEntry {
+    mv: u16, //Packed uci, see pack_uci
+    pub n: u32,
+    pub white: u32, //Games won by white after the move
+    pub draws: u32,
+    pub black: u32,
+}
+
+impl MoveEntry {
+    pub fn uci(&self) -> Uci {
+        unpack_uci(self.mv)
+    }
 
-    for m in moves {
-        if m.n > n_max {
-            pop = &m.mv;
-            n_max = m.n;
+    fn count(&mut self, outcome: Option<Outcome>) {
+        self.n += 1;
+        match outcome {
+            Some(Outcome::Decisive {
+                winner: Color::White,
+            }) => self.white += 1,
+            Some(Outcome::Decisive {
+                winner: Color::Black,
+            }) => self.black += 1,
+            Some(Outcome::Draw) => self.draws += 1,
+            None => {}
         }
     }
-    pop.to_string()
 }
 
-#[derive(Debug, Serialize)]
-pub struct MoveEntry {
-    mv: String,
-    n: usize,
+impl Serialize for MoveEntry {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut entry = serializer.serialize_struct("MoveEntry", 5)?;
+        entry.serialize_field("mv", &self.uci().to_string())?;
+        entry.serialize_field("n", &self.n)?;
+        entry.serialize_field("white", &self.white)?;
+        entry.serialize_field("draws", &self.draws)?;
+        entry.serialize_field("black", &self.black)?;
+        entry.end()
+    }
+}
+
+fn game_outcome(game: &Game) -> Option<Outcome> {
+    match &game.winner {
+        Some(winner) if winner.eq_ignore_ascii_case(&game.white) => Some(Outcome::Decisive {
+            winner: Color::White,
+        }),
+        Some(_) => Some(Outcome::Decisive {
+            winner: Color::Black,
+        }),
+        None => Some(Outcome::Draw),
+    }
 }
 
+/// Move counts and results per position, keyed by the Zobrist hash of the position.
+/// Only the first `max_ply` plies of each game are counted, so large PGN dumps can be
+/// streamed through it and pruned with `prune`.
 pub struct OpeningTree {
     games: usize,
+    max_ply: usize,
+    positions: HashMap<u64, Vec<MoveEntry>>,
     pos: Chess,
+    path: Vec<(u64, u16)>, //Moves of the current game, counted once the result is known
+    outcome: Option<Outcome>,
     success: bool,
-    pub move_stat: HashMap<String, Vec<MoveEntry>>,
 }
 
 impl OpeningTree {
     pub fn new() -> Self {
+        Self::with_max_ply(usize::MAX)
+    }
+
+    pub fn with_max_ply(max_ply: usize) -> Self {
         Self {
             games: 0,
+            max_ply,
+            positions: HashMap::new(),
             pos: Chess::default(),
+            path: Vec::new(),
+            outcome: None,
             success: true,
-            move_stat: HashMap::new(),
         }
     }
 
-    fn count_position(&mut self, mv: &Move) {
-        let fen = fen::epd(&self.pos);
-        let uci = mv.to_uci(CastlingMode::Standard);
-        match self.move_stat.get_mut(&fen) {
-            Some(entries) => insert_move(entries, &format!("{}", uci)),
-            None => {
-                let entry = vec![MoveEntry {
-                    mv: uci.to_string(),
-                    n: 1,
-                }];
-                self.move_stat.insert(fen, entry);
+    pub fn games(&self) -> usize {
+        self.games
+    }
+
+    pub fn positions(&self) -> usize {
+        self.positions.len()
+    }
+
+    pub fn moves(&self, pos: &Chess) -> &[MoveEntry] {
+        self.positions
+            .get(&pos.zobrist_hash::<u64>())
+            .map(|entries| entries.as_slice())
+            .unwrap_or(&[])
+    }
+
+    fn push_move(&mut self, m: &Move) -> bool {
+        match pack_uci(&m.to_uci(CastlingMode::Standard)) {
+            Some(mv) => {
+                self.path.push((self.pos.zobrist_hash::<u64>(), mv));
+                self.pos.play_unchecked(m);
+                true
             }
-        };
+            None => false,
+        }
+    }
+
+    fn commit_game(&mut self) {
+        self.games += 1;
+
+        for (key, mv) in self.path.drain(..) {
+            let entries = self.positions.entry(key).or_default();
+            let idx = match entries.iter().position(|entry| entry.mv == mv) {
+                Some(idx) => idx,
+                None => {
+                    entries.push(MoveEntry {
+                        mv,
+                        n: 0,
+                        white: 0,
+                        draws: 0,
+                        black: 0,
+                    });
+                    entries.len() - 1
+                }
+            };
+            entries[idx].count(self.outcome);
+        }
+    }
+
+    pub fn add_uci_game(&mut self, moves: &[String], outcome: Option<Outcome>) {
+        //Stored games keep their moves in uci, they skip the pgn visitor
+        self.pos = Chess::default();
+        self.path.clear();
+        self.outcome = outcome;
+
+        for mv in moves.iter().take(self.max_ply) {
+            let m = match mv
+                .parse::<Uci>()
+                .ok()
+                .and_then(|uci| uci.to_move(&self.pos).ok())
+            {
+                Some(m) => m,
+                None => break,
+            };
+            if !self.push_move(&m) {
+                break;
+            }
+        }
+
+        self.commit_game();
+    }
+
+    pub fn add_game(&mut self, game: &Game) {
+        self.add_uci_game(&game.moves, game_outcome(game));
+    }
+
+    /// Drops moves played fewer than `min_count` times and positions left without moves.
+    pub fn prune(&mut self, min_count: u32) {
+        self.positions.retain(|_, entries| {
+            entries.retain(|entry| entry.n >= min_count);
+            entries.shrink_to_fit();
+            !entries.is_empty()
+        });
+    }
+
+    /// Reads the games one at a time, only the counters stay in memory. With a `min_count`
+    /// above 1 the tree is pruned every `PRUNE_INTERVAL` games and once at the end, so a
+    /// move pruned early starts counting again from zero.
+    pub fn from_reader<R: Read>(reader: R, max_ply: usize, min_count: u32) -> io::Result<Self> {
+        let mut reader = BufferedReader::new(reader);
+        let mut tree = OpeningTree::with_max_ply(max_ply);
+        let mut since_prune = 0;
+        while reader.read_game(&mut tree)?.is_some() {
+            since_prune += 1;
+            if min_count > 1 && since_prune == PRUNE_INTERVAL {
+                tree.prune(min_count);
+                since_prune = 0;
+            }
+        }
+        if min_count > 1 {
+            tree.prune(min_count);
+        }
+
+        Ok(tree)
+    }
+
+    pub fn from_pgn_file(path: &str, max_ply: usize, min_count: u32) -> io::Result<Self> {
+        Self::from_reader(File::open(path)?, max_ply, min_count)
+    }
+
+    /// Positions reachable from `start`, keyed by epd, for clients that cannot replay hashes.
+    pub fn export(&self, start: &Chess, max_ply: usize) -> HashMap<String, Vec<MoveEntry>> {
+        let mut exported = HashMap::new();
+        let mut visited = HashSet::new();
+        let mut stack = vec![(start.clone(), 0)];
+
+        while let Some((pos, ply)) = stack.pop() {
+            if ply >= max_ply || !visited.insert(pos.zobrist_hash::<u64>()) {
+                continue;
+            }
+            let entries = self.moves(&pos);
+            if entries.is_empty() {
+                continue;
+            }
+
+            for entry in entries {
+                if let Ok(m) = entry.uci().to_move(&pos) {
+                    let mut next = pos.clone();
+                    next.play_unchecked(&m);
+                    stack.push((next, ply + 1));
+                }
+            }
+            exported.insert(fen::epd(&pos), entries.to_vec());
+        }
+
+        exported
     }
 }
 
@@ -90,37 +279,42 @@ impl Visitor for OpeningTree {
     type Result = bool;
 
     fn begin_game(&mut self) {
-        self.games += 1;
         self.pos = Chess::default();
+        self.path.clear();
+        self.outcome = None;
         self.success = true;
     }
 
     fn header(&mut self, key: &[u8], value: RawHeader<'_>) {
-        // Support games from a non-standard starting position.
-        if key == b"FEN" {
-            let fen = match Fen::from_ascii(value.as_bytes()) {
-                Ok(fen) => fen,
-                Err(err) => {
-                    eprintln!(
-                        "invalid fen header in game {}: {} ({:?})",
-                        self.games, err, value
-                    );
-                    self.success = false;
-                    return;
-                }
-            };
+        match key {
+            // Support games from a non-standard starting position.
+            b"FEN" => {
+                let fen = match Fen::from_ascii(value.as_bytes()) {
+                    Ok(fen) => fen,
+                    Err(_) => {
+                        self.success = false;
+                        return;
+                    }
+                };
 
-            self.pos = match fen.position(CastlingMode::Chess960) {
-                Ok(pos) => pos,
-                Err(err) => {
-                    eprintln!(
-                        "illegal fen header in game {}: {} ({})",
-                        self.games, err, fen
-                    );
-                    self.success = false;
-                    return;
+                match fen.position(CastlingMode::Chess960) {
+                    Ok(pos) => self.pos = pos,
+                    Err(_) => self.success = false,
                 }
-            };
+            }
+            b"Result" => {
+                self.outcome = match value.as_bytes() {
+                    b"1-0" => Some(Outcome::Decisive {
+                        winner: Color::White,
+                    }),
+                    b"0-1" => Some(Outcome::Decisive {
+                        winner: Color::Black,
+                    }),
+                    b"1/2-1/2" => Some(Outcome::Draw),
+                    _ => None,
+                }
+            }
+            _ => {}
         }
     }
 
@@ -133,57 +327,167 @@ impl Visitor for OpeningTree {
     }
 
     fn san(&mut self, san_plus: SanPlus) {
-        if self.success {
-            match san_plus.san.to_move(&self.pos) {
-                Ok(m) => {
-                    self.count_position(&m);
-                    self.pos.play_unchecked(&m)
-                }
-                Err(err) => {
-                    eprintln!("error in game {}: {} {}", self.games, err, san_plus);
+        if !self.success || self.path.len() >= self.max_ply {
+            return;
+        }
+
+        match san_plus.san.to_move(&self.pos) {
+            Ok(m) => {
+                if !self.push_move(&m) {
                     self.success = false;
                 }
             }
+            Err(_) => self.success = false,
         }
     }
 
     fn end_game(&mut self) -> Self::Result {
+        //A broken game still counts the moves up to the error
+        if self.success || !self.path.is_empty() {
+            self.commit_game();
+        }
         self.success
     }
 }
 
-pub fn parse_common_moves() -> io::Result<()> {
-    let mut success = true;
-
-    let file = File::open("test2.pgn")?;
-    let mut reader = BufferedReader::new(file);
+pub enum TreeSource {
+    File(String),
+    Lichess { player: String, games: usize },
+    Database(GameFilter),
+}
 
-    let mut validator = OpeningTree::new();
-    while let Some(ok) = reader.read_game(&mut validator)? {
-        success &= ok;
+/// Builds the tree from the source, dropping moves played fewer than `min_count` times.
+pub async fn load_tree(
+    db: Option<&Database>,
+    source: TreeSource,
+    max_ply: usize,
+    min_count: u32,
+) -> Result<OpeningTree> {
+    match source {
+        TreeSource::File(path) => Ok(OpeningTree::from_pgn_file(&path, max_ply, min_count)?),
+        TreeSource::Lichess { player, games } => {
+            let pgn = get_games_player(&player, games).await?;
+            Ok(OpeningTree::from_reader(
+                pgn.as_bytes(),
+                max_ply,
+                min_count,
+            )?)
+        }
+        TreeSource::Database(filter) => {
+            let db = db.ok_or_else(|| anyhow!("a database connection is needed"))?;
+            let page = db.find_games(filter).await?;
+            let mut tree = OpeningTree::with_max_ply(max_ply);
+            for game in &page.games {
+                tree.add_game(game);
+            }
+            tree.prune(min_count);
+            Ok(tree)
+        }
     }
+}
 
-    if !success {
-        ::std::process::exit(1);
+pub fn start_position(fen: Option<&str>, moves: &[String]) -> Result<Chess> {
+    //Moves can be uci or san, played from the fen when there is one
+    let mut pos = match fen {
+        Some(fen) => Fen::from_ascii(fen.as_bytes())
+            .map_err(|_| anyhow!("invalid fen {}", fen))?
+            .position(CastlingMode::Chess960)
+            .map_err(|_| anyhow!("illegal position {}", fen))?,
+        None => Chess::default(),
+    };
+
+    for mv in moves {
+        let m = match mv
+            .parse::<Uci>()
+            .ok()
+            .and_then(|uci| uci.to_move(&pos).ok())
+        {
+            Some(m) => m,
+            None => mv
+                .parse::<SanPlus>()
+                .map_err(|_| anyhow!("invalid move {}", mv))?
+                .san
+                .to_move(&pos)
+                .map_err(|_| anyhow!("illegal move {}", mv))?,
+        };
+        pos.play_unchecked(&m);
     }
 
-    let stats = validator.move_stat;
-    let mut pos = Chess::default();
+    Ok(pos)
+}
 
-    for _ in 0..80 {
-        let fen = fen::epd(&pos);
-        match stats.get(&fen) {
-            Some(ms) => {
-                let most_popular = find_most_popular(ms);
-                let m: Uci = most_popular.parse().unwrap();
-                println!("{}", m);
-                pos.play_unchecked(&m.to_move(&pos).unwrap());
-            }
-            None => {
-                break;
-            }
-        }
+pub fn start_ply(pos: &Chess) -> usize {
+    //Plies played to reach the position, so the tree can be cut just past the lines we print
+    (pos.fullmoves().get() as usize - 1) * 2 + usize::from(pos.turn() == Color::Black)
+}
+
+#[derive(Debug, Serialize)]
+pub struct Continuation {
+    pub san: String,
+    pub uci: String,
+    pub games: u32,
+    pub frequency: f64, //Share of the games from the position that played this move
+    pub white: u32,
+    pub draws: u32,
+    pub black: u32,
+}
+
+#[derive(Debug, Serialize)]
+pub struct PlyContinuations {
+    pub ply: usize,
+    pub line: Vec<String>, //San moves from the start position
+    pub moves: Vec<Continuation>,
+}
+
+/// Top `top` continuations at every ply of the most popular line from `start`.
+pub fn common_moves(
+    tree: &OpeningTree,
+    start: &Chess,
+    depth: usize,
+    top: usize,
+) -> Vec<PlyContinuations> {
+    let mut plies = Vec::new();
+    let mut pos = start.clone();
+    let mut line = Vec::new();
+
+    for ply in 0..depth {
+        let mut entries = tree.moves(&pos).to_vec();
+        entries.sort_by_key(|entry| Reverse(entry.n));
+        let total = entries.iter().map(|entry| entry.n).sum::<u32>();
+
+        let moves = entries
+            .iter()
+            .filter_map(|entry| {
+                let m = entry.uci().to_move(&pos).ok()?;
+                Some((entry, m))
+            })
+            .collect::<Vec<_>>();
+        let (_, most_popular) = match moves.first() {
+            Some(first) => first.clone(),
+            None => break,
+        };
+
+        plies.push(PlyContinuations {
+            ply: start_ply(start) + ply + 1,
+            line: line.clone(),
+            moves: moves
+                .iter()
+                .take(top)
+                .map(|(entry, m)| Continuation {
+                    san: San::from_move(&pos, m).to_string(),
+                    uci: entry.uci().to_string(),
+                    games: entry.n,
+                    frequency: entry.n as f64 / total as f64,
+                    white: entry.white,
+                    draws: entry.draws,
+                    black: entry.black,
+                })
+                .collect(),
+        });
+
+        line.push(San::from_move(&pos, &most_popular).to_string());
+        pos.play_unchecked(&most_popular);
     }
 
-    Ok(())
+    plies
 }
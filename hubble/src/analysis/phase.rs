@@ -0,0 +1,233 @@
+use hubble_db::models::game::{Blunders, Game};
+use hubble_db::models::game_filter::{GameFilter, SortField};
+use hubble_db::{Database, DbError};
+use serde::Serialize;
+use shakmaty::{uci::Uci, Bitboard, Board, Chess, Color, File, Position, Rank, Setup, Square};
+
+#[derive(Debug, Serialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum Phase {
+    Opening,
+    MiddleGame,
+    EndGame,
+}
+
+/// Thresholds of the phase rules, the defaults are the ones of lichess' divider
+/// plus an endgame rule on the material left.
+pub struct PhaseOptions {
+    pub middle_game_pieces: u32, //Knights, bishops, rooks and queens left when the middlegame starts at the latest
+    pub backrank_pieces: u32,    //A side with fewer pieces than this on its back rank has developed
+    pub mixedness: i32,          //Pieces of both sides this mixed up mean the middlegame started
+    pub end_game_pieces: u32,
+    pub end_game_material: u32, //Non-pawn material of both sides, knights and bishops worth 3, 0 turns the rule off
+}
+
+impl Default for PhaseOptions {
+    fn default() -> Self {
+        Self {
+            middle_game_pieces: 10,
+            backrank_pieces: 4,
+            mixedness: 150,
+            end_game_pieces: 6,
+            end_game_material: 26,
+        }
+    }
+}
+
+/// First plies of the middlegame and of the endgame, none for a phase the game never
+/// reached. A position reached by the move at a ply belongs to that ply's phase.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Division {
+    pub middle_game: Option<i32>,
+    pub end_game: Option<i32>,
+}
+
+impl Division {
+    pub fn phase(&self, ply: i32) -> Phase {
+        if matches!(self.end_game, Some(end) if ply >= end) {
+            Phase::EndGame
+        } else if matches!(self.middle_game, Some(middle) if ply >= middle) {
+            Phase::MiddleGame
+        } else {
+            Phase::Opening
+        }
+    }
+}
+
+fn majors_and_minors(board: &Board) -> u32 {
+    (board.occupied() & !board.kings() & !board.pawns()).count() as u32
+}
+
+fn material(board: &Board) -> u32 {
+    let minors = (board.knights() | board.bishops()).count() as u32;
+    minors * 3 + board.rooks().count() as u32 * 5 + board.queens().count() as u32 * 9
+}
+
+fn backrank_sparse(board: &Board, options: &PhaseOptions) -> bool {
+    [Color::White, Color::Black].iter().any(|&color| {
+        let backrank = board.by_color(color) & Bitboard::from_rank(color.backrank());
+        (backrank.count() as u32) < options.backrank_pieces
+    })
+}
+
+// How much a 2x2 region with this many white and black pieces adds to the mixedness,
+// `y` is the rank of its lower squares from 1 to 7. Pieces count more the deeper they
+// are in the enemy camp and most when both sides share the region.
+fn region_score(white: u32, black: u32, y: i32) -> i32 {
+    match (white, black) {
+        (0, 0) => 0,
+        (1, 0) => 1 + (8 - y),
+        (2, 0) if y > 2 => 2 + (y - 2),
+        (3, 0) | (4, 0) if y > 1 => 3 + (y - 1),
+        (0, 1) => 1 + y,
+        (1, 1) => 5 + (3 - y).abs(),
+        (2, 1) => 4 + y,
+        (3, 1) => 5 + y,
+        (0, 2) if y < 6 => 2 + (6 - y),
+        (1, 2) => 4 + (6 - y),
+        (2, 2) => 7,
+        (0, 3) | (0, 4) if y < 7 => 3 + (7 - y),
+        (1, 3) => 5 + (6 - y),
+        _ => 0,
+    }
+}
+
+/// How far the pieces of both sides have moved into each other, summed over every
+/// 2x2 region of the board the way lichess does.
+pub fn mixedness(board: &Board) -> i32 {
+    let mut mix = 0;
+    for y in 0..7 {
+        for x in 0..7 {
+            let mut region = Bitboard::EMPTY;
+            for (dx, dy) in [(0, 0), (1, 0), (0, 1), (1, 1)] {
+                region.add(Square::from_coords(File::new(x + dx), Rank::new(y + dy)));
+            }
+            let white = (board.white() & region).count() as u32;
+            let black = (board.black() & region).count() as u32;
+            mix += region_score(white, black, y as i32 + 1);
+        }
+    }
+    mix
+}
+
+pub fn is_middle_game(board: &Board, options: &PhaseOptions) -> bool {
+    majors_and_minors(board) <= options.middle_game_pieces
+        || backrank_sparse(board, options)
+        || mixedness(board) > options.mixedness
+}
+
+pub fn is_end_game(board: &Board, options: &PhaseOptions) -> bool {
+    majors_and_minors(board) <= options.end_game_pieces
+        || material(board) <= options.end_game_material
+}
+
+/// Phases of the moves played from `start`, none when a move does not replay from it.
+/// A start position that is already past the opening puts the first move in its phase.
+pub fn divide(start: &Chess, moves: &[String], options: &PhaseOptions) -> Option<Division> {
+    let mut division = Division::default();
+    let mut mark = |board: &Board, ply: i32| {
+        if division.middle_game.is_none() && is_middle_game(board, options) {
+            division.middle_game = Some(ply);
+        }
+        if division.end_game.is_none() && is_end_game(board, options) {
+            division.end_game = Some(ply);
+        }
+    };
+
+    let mut pos = start.clone();
+    mark(pos.board(), 0);
+    for (ply, uci) in moves.iter().enumerate() {
+        let m = uci.parse::<Uci>().ok()?.to_move(&pos).ok()?;
+        pos.play_unchecked(&m);
+        mark(pos.board(), ply as i32);
+    }
+
+    //Trading down fast can skip the middlegame rules, the endgame still ends the middlegame
+    if let Some(end) = division.end_game {
+        division.middle_game = Some(division.middle_game.map_or(end, |middle| middle.min(end)));
+    }
+    Some(division)
+}
+
+/// Phases of a stored game, whose moves are played from the standard start position.
+/// Games from a custom position are stored without it, so they give none.
+pub fn divide_game(game: &Game, options: &PhaseOptions) -> Option<Division> {
+    divide(&Chess::default(), &game.moves, options)
+}
+
+/// Sorts blunder plies into the phases of the division, motifs are left empty.
+pub fn group_blunders(plies: &[i32], division: &Division) -> Blunders {
+    let mut grouped = Blunders::empty();
+    for &ply in plies {
+        match division.phase(ply) {
+            Phase::Opening => grouped.opening.push(ply),
+            Phase::MiddleGame => grouped.middle_game.push(ply),
+            Phase::EndGame => grouped.end_game.push(ply),
+        }
+    }
+    grouped
+}
+
+/// Recomputes the phases of already analysed games and regroups their blunders, without
+/// running the engine again. Games whose moves don't replay are left as they are.
+/// Returns how many games changed.
+pub async fn recompute_phases(
+    db: &Database,
+    player: Option<&str>,
+    batch_size: i64,
+    options: &PhaseOptions,
+) -> Result<usize, DbError> {
+    let mut changed = 0;
+    let mut offset = 0;
+
+    loop {
+        let filter = GameFilter {
+            player: player.map(str::to_string),
+            sort: SortField::Id,
+            descending: false,
+            limit: batch_size,
+            offset,
+            ..GameFilter::default()
+        };
+        let page = db.find_games(filter).await?;
+        if page.games.is_empty() {
+            break;
+        }
+        offset += page.games.len() as i64;
+
+        for mut game in page.games {
+            let division = match divide_game(&game, options) {
+                Some(division) => division,
+                None => continue,
+            };
+            let mut plies = game
+                .blunders
+                .opening
+                .iter()
+                .chain(&game.blunders.middle_game)
+                .chain(&game.blunders.end_game)
+                .copied()
+                .collect::<Vec<_>>();
+            plies.sort_unstable();
+            let mut blunders = group_blunders(&plies, &division);
+
+            if division.middle_game == game.middle_game
+                && division.end_game == game.end_game
+                && blunders.opening == game.blunders.opening
+                && blunders.middle_game == game.blunders.middle_game
+                && blunders.end_game == game.blunders.end_game
+            {
+                continue;
+            }
+
+            blunders.motifs = std::mem::take(&mut game.blunders.motifs);
+            game.middle_game = division.middle_game;
+            game.end_game = division.end_game;
+            game.blunders = blunders;
+            db.set_game_phases(game).await?;
+            changed += 1;
+        }
+    }
+
+    Ok(changed)
+}
@@ -0,0 +1,264 @@
+use crate::analysis::opening_tree::OpeningTree;
+pub use crate::analysis::phase::Phase as BlunderPhase;
+use crate::analysis::{OpeningClassifier, OpeningCounter};
+use crate::pgn::game_to_pgn;
+use hubble_db::models::game::Game;
+use hubble_db::models::game_filter::{Color, GameFilter};
+use hubble_db::{Database, DbError};
+use pgn_reader::BufferedReader;
+use serde::Serialize;
+use shakmaty::{san::San, Chess, Position};
+use std::cmp::Reverse;
+
+#[derive(Debug, Serialize)]
+pub struct FrequentLine {
+    pub moves: Vec<String>, //San
+    pub games: u32,
+}
+
+#[derive(Debug, Serialize)]
+pub struct OpeningScore {
+    pub name: String,
+    pub won: u32,
+    pub drawn: u32,
+    pub lost: u32,
+    pub score: f64,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ColorPreparation {
+    pub color: Color,
+    pub games: usize,
+    pub lines: Vec<FrequentLine>,
+    pub worst_openings: Vec<OpeningScore>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct BlunderProfile {
+    pub opening: usize,
+    pub middle_game: usize,
+    pub end_game: usize,
+    pub typical: Option<BlunderPhase>, //Phase with the most blunders, none without blunders
+}
+
+#[derive(Debug, Serialize)]
+pub struct PreparationReport {
+    pub opponent: String,
+    pub games: usize,
+    pub white: ColorPreparation,
+    pub black: ColorPreparation,
+    pub blunders: BlunderProfile,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct PreparationOptions {
+    pub line_depth: usize, //Plies per frequent line
+    pub max_lines: usize,
+    pub min_games: u32, //Lines and openings seen fewer times are left out
+    pub max_openings: usize,
+}
+
+impl Default for PreparationOptions {
+    fn default() -> Self {
+        Self {
+            line_depth: 8,
+            max_lines: 5,
+            min_games: 2,
+            max_openings: 5,
+        }
+    }
+}
+
+fn is_player_color(game: &Game, player: &str, color: Color) -> bool {
+    match color {
+        Color::White => game.white.eq_ignore_ascii_case(player),
+        Color::Black => game.black.eq_ignore_ascii_case(player),
+    }
+}
+
+fn collect_lines(
+    tree: &OpeningTree,
+    pos: &Chess,
+    line: &mut Vec<String>,
+    games: u32,
+    options: &PreparationOptions,
+    lines: &mut Vec<FrequentLine>,
+) {
+    let mut extended = false;
+
+    if line.len() < options.line_depth {
+        for entry in tree
+            .moves(pos)
+            .iter()
+            .filter(|entry| entry.n >= options.min_games)
+        {
+            let m = match entry.uci().to_move(pos) {
+                Ok(m) => m,
+                Err(_) => continue,
+            };
+            let mut next = pos.clone();
+            next.play_unchecked(&m);
+
+            line.push(San::from_move(pos, &m).to_string());
+            collect_lines(tree, &next, line, entry.n, options, lines);
+            line.pop();
+            extended = true;
+        }
+    }
+
+    //Only the end of a line is kept, its prefixes are part of it
+    if !extended && !line.is_empty() {
+        lines.push(FrequentLine {
+            moves: line.clone(),
+            games,
+        });
+    }
+}
+
+pub fn frequent_lines(tree: &OpeningTree, options: &PreparationOptions) -> Vec<FrequentLine> {
+    let mut lines = Vec::new();
+    collect_lines(
+        tree,
+        &Chess::default(),
+        &mut Vec::new(),
+        tree.games() as u32,
+        options,
+        &mut lines,
+    );
+
+    lines.sort_by_key(|line| Reverse(line.games));
+    lines.truncate(options.max_lines);
+    lines
+}
+
+fn worst_openings(
+    games: &[&Game],
+    player: &str,
+    color: Color,
+    classifier: &OpeningClassifier,
+    options: &PreparationOptions,
+) -> Vec<OpeningScore> {
+    let pgn = games
+        .iter()
+        .filter_map(|game| game_to_pgn(game))
+        .collect::<String>();
+    let mut counter =
+        OpeningCounter::new(classifier, player.to_string(), Some(color == Color::White));
+    let mut reader = BufferedReader::new_cursor(pgn.as_bytes());
+    while let Ok(Some(_)) = reader.read_game(&mut counter) {}
+
+    let mut openings = counter
+        .openings
+        .into_iter()
+        .filter(|(_, result)| result.total() >= options.min_games)
+        .map(|(name, result)| OpeningScore {
+            name,
+            won: result.won,
+            drawn: result.tie,
+            lost: result.lost,
+            score: result.score(),
+        })
+        .collect::<Vec<_>>();
+
+    openings.sort_by(|a, b| a.score.total_cmp(&b.score));
+    openings.truncate(options.max_openings);
+    openings
+}
+
+fn color_preparation(
+    games: &[Game],
+    player: &str,
+    color: Color,
+    classifier: &OpeningClassifier,
+    options: &PreparationOptions,
+) -> ColorPreparation {
+    let games = games
+        .iter()
+        .filter(|game| is_player_color(game, player, color))
+        .collect::<Vec<_>>();
+
+    let mut tree = OpeningTree::with_max_ply(options.line_depth);
+    for game in &games {
+        tree.add_game(game);
+    }
+
+    ColorPreparation {
+        color,
+        games: games.len(),
+        lines: frequent_lines(&tree, options),
+        worst_openings: worst_openings(&games, player, color, classifier, options),
+    }
+}
+
+pub fn blunder_profile(games: &[Game], player: &str) -> BlunderProfile {
+    //Blunders are stored as ply indexes, even plies are white's moves
+    let mut profile = BlunderProfile {
+        opening: 0,
+        middle_game: 0,
+        end_game: 0,
+        typical: None,
+    };
+
+    for game in games {
+        let is_white = game.white.eq_ignore_ascii_case(player);
+        let own = |plies: &Vec<i32>| {
+            plies
+                .iter()
+                .filter(|ply| (*ply % 2 == 0) == is_white)
+                .count()
+        };
+        profile.opening += own(&game.blunders.opening);
+        profile.middle_game += own(&game.blunders.middle_game);
+        profile.end_game += own(&game.blunders.end_game);
+    }
+
+    let phases = [
+        (profile.opening, BlunderPhase::Opening),
+        (profile.middle_game, BlunderPhase::MiddleGame),
+        (profile.end_game, BlunderPhase::EndGame),
+    ];
+    profile.typical = phases
+        .iter()
+        .filter(|(count, _)| *count > 0)
+        .max_by_key(|(count, _)| *count)
+        .map(|(_, phase)| *phase);
+
+    profile
+}
+
+pub fn build_preparation(
+    games: &[Game],
+    opponent: &str,
+    classifier: &OpeningClassifier,
+    options: PreparationOptions,
+) -> PreparationReport {
+    PreparationReport {
+        opponent: opponent.to_string(),
+        games: games.len(),
+        white: color_preparation(games, opponent, Color::White, classifier, &options),
+        black: color_preparation(games, opponent, Color::Black, classifier, &options),
+        blunders: blunder_profile(games, opponent),
+    }
+}
+
+/// Summarises the opponent's latest stored games, lichess::analyse_player fetches them.
+pub async fn prepare(
+    db: &Database,
+    classifier: &OpeningClassifier,
+    opponent: &str,
+    num_games: i64,
+    options: PreparationOptions,
+) -> Result<PreparationReport, DbError> {
+    let filter = GameFilter {
+        player: Some(opponent.to_string()),
+        limit: num_games,
+        ..GameFilter::default()
+    };
+    let page = db.find_games(filter).await?;
+    Ok(build_preparation(
+        &page.games,
+        opponent,
+        classifier,
+        options,
+    ))
+}
@@ -0,0 +1,227 @@
+use crate::analysis::analyser::{get_engine, search_best_two};
+use crate::analysis::motif::Motif;
+use hubble_db::models::game::Game;
+use hubble_db::models::game_filter::GameFilter;
+use hubble_db::models::player_key;
+use hubble_db::models::puzzle::Puzzle;
+use hubble_db::{Database, DbError};
+use shakmaty::{fen, uci::Uci, CastlingMode, Chess, Move, Position};
+
+// Winning chances, from -1 to 1, the solution has to reach for the player
+pub const PUZZLE_MIN_CHANCES: f64 = 0.3;
+// How far ahead of every other move the solution has to be, in winning chances
+pub const PUZZLE_MIN_GAP: f64 = 0.3;
+
+const LICHESS_CSV_HEADER: &str =
+    "PuzzleId,FEN,Moves,Rating,RatingDeviation,Popularity,NbPlays,Themes,GameUrl,OpeningTags";
+
+/// Winning chances for the side with the score, the same curve lichess uses.
+pub fn winning_chances(centipawns: i32) -> f64 {
+    2. / (1. + (-0.00368208 * centipawns as f64).exp()) - 1.
+}
+
+/// The best move makes a puzzle when it wins and no other move comes close.
+/// A forced move has no second score and is never a puzzle on its own.
+pub fn is_unique_solution(best: i32, second: Option<i32>) -> bool {
+    let best = winning_chances(best);
+    match second {
+        Some(second) => {
+            best >= PUZZLE_MIN_CHANCES && best - winning_chances(second) >= PUZZLE_MIN_GAP
+        }
+        None => false,
+    }
+}
+
+/// Position before one of the player's blunders.
+pub struct BlunderPosition {
+    pub ply: usize,
+    pub position: Chess,
+    pub previous: Option<(Chess, Move)>, //The opponent's move leading to the position
+    pub played: Move,
+}
+
+fn blunder_plies(game: &Game) -> Vec<i32> {
+    let mut plies = game
+        .blunders
+        .opening
+        .iter()
+        .chain(&game.blunders.middle_game)
+        .chain(&game.blunders.end_game)
+        .copied()
+        .collect::<Vec<_>>();
+    plies.sort_unstable();
+    plies
+}
+
+/// Positions before the player's own blunders, found by replaying the game.
+pub fn blunder_positions(game: &Game, player: &str) -> Vec<BlunderPosition> {
+    let side = if game.white.eq_ignore_ascii_case(player) {
+        0
+    } else if game.black.eq_ignore_ascii_case(player) {
+        1
+    } else {
+        return Vec::new();
+    };
+    let plies = blunder_plies(game);
+
+    let mut positions = Vec::new();
+    let mut previous: Option<(Chess, Move)> = None;
+    let mut pos = Chess::default();
+    for (ply, uci) in game.moves.iter().enumerate() {
+        let m = match uci
+            .parse::<Uci>()
+            .ok()
+            .and_then(|uci| uci.to_move(&pos).ok())
+        {
+            Some(m) => m,
+            None => break,
+        };
+        if ply % 2 == side && plies.binary_search(&(ply as i32)).is_ok() {
+            positions.push(BlunderPosition {
+                ply,
+                position: pos.clone(),
+                previous: previous.clone(),
+                played: m.clone(),
+            });
+        }
+        previous = Some((pos.clone(), m.clone()));
+        pos.play_unchecked(&m);
+    }
+
+    positions
+}
+
+fn phase_theme(game: &Game, ply: i32) -> &'static str {
+    if game.blunders.opening.contains(&ply) {
+        "opening"
+    } else if game.blunders.middle_game.contains(&ply) {
+        "middlegame"
+    } else {
+        "endgame"
+    }
+}
+
+/// Builds the puzzle from the engine's search of the position, none when the solution
+/// is not unique or is the move played in the game.
+pub fn puzzle_from_search(
+    game: &Game,
+    player: &str,
+    blunder: &BlunderPosition,
+    best: i32,
+    line: &[String],
+    second: Option<i32>,
+) -> Option<Puzzle> {
+    let played = blunder.played.to_uci(CastlingMode::Standard).to_string();
+    let solution = line.first()?;
+    if *solution == played || !is_unique_solution(best, second) {
+        return None;
+    }
+
+    let ply = blunder.ply as i32;
+    let mut themes = vec![phase_theme(game, ply).to_string()];
+    let mut motifs = game.blunders.motifs.get(&ply).into_iter().flatten();
+    if motifs.any(|motif| motif == Motif::MissedFork.as_str()) {
+        themes.push(String::from("fork"));
+    }
+    //The solution is a single move, so only a mate in one is complete
+    if best == 100_000 - 1 {
+        themes.push(String::from("mate"));
+        themes.push(String::from("mateIn1"));
+    }
+    themes.push(String::from("oneMove"));
+
+    Some(Puzzle {
+        game_id: game.id.clone(),
+        ply,
+        player: player_key(player),
+        fen: fen::fen(&blunder.position),
+        previous_fen: blunder.previous.as_ref().map(|(pos, _)| fen::fen(pos)),
+        previous_move: blunder
+            .previous
+            .as_ref()
+            .map(|(_, m)| m.to_uci(CastlingMode::Standard).to_string()),
+        solution: vec![solution.clone()],
+        played,
+        best_score: best,
+        second_score: second,
+        themes,
+        rating: if ply % 2 == 0 {
+            game.white_rating
+        } else {
+            game.black_rating
+        },
+    })
+}
+
+/// Looks for a puzzle at each of the player's blunders in their latest stored games and
+/// replaces the puzzles stored for those games. Runs one MultiPV search per blunder, for the
+/// best move and the best of the other moves.
+pub async fn generate_puzzles(
+    db: &Database,
+    player: &str,
+    max_games: i64,
+) -> Result<usize, DbError> {
+    let filter = GameFilter {
+        player: Some(player.to_string()),
+        limit: max_games,
+        ..GameFilter::default()
+    };
+    let page = db.find_games(filter).await?;
+    let engine = get_engine().await;
+
+    let mut puzzles = Vec::new();
+    for game in &page.games {
+        for blunder in blunder_positions(game, player) {
+            let (best, line, second) = search_best_two(&blunder.position, &engine).await;
+            if let Some(puzzle) = puzzle_from_search(game, player, &blunder, best, &line, second) {
+                puzzles.push(puzzle);
+            }
+        }
+    }
+
+    let ids = page.games.iter().map(|game| game.id.clone()).collect();
+    db.replace_puzzles(player, ids, puzzles).await
+}
+
+fn csv_field(value: &str) -> String {
+    if value.contains(',') || value.contains('"') {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_string()
+    }
+}
+
+/// Puzzles in the lichess puzzle database format. Lichess puzzles start before the
+/// opponent's last move, so puzzles from the first ply of a game are left out.
+pub fn puzzles_to_csv(puzzles: &[Puzzle]) -> String {
+    let mut csv = format!("{}\n", LICHESS_CSV_HEADER);
+
+    for puzzle in puzzles {
+        let (previous_fen, previous_move) = match (&puzzle.previous_fen, &puzzle.previous_move) {
+            (Some(fen), Some(m)) => (fen, m),
+            _ => continue,
+        };
+        let moves = std::iter::once(previous_move)
+            .chain(&puzzle.solution)
+            .cloned()
+            .collect::<Vec<_>>();
+
+        let row = [
+            format!("{}-{}", puzzle.game_id, puzzle.ply),
+            previous_fen.clone(),
+            moves.join(" "),
+            puzzle.rating.unwrap_or(1500).to_string(),
+            String::from("500"), //Never played, so as uncertain as a new lichess puzzle
+            String::from("0"),
+            String::from("0"),
+            puzzle.themes.join(" "),
+            format!("https://lichess.org/{}#{}", puzzle.game_id, puzzle.ply),
+            String::new(),
+        ];
+        let row = row.iter().map(|field| csv_field(field)).collect::<Vec<_>>();
+        csv.push_str(&row.join(","));
+        csv.push('\n');
+    }
+
+    csv
+}
@@ -0,0 +1,168 @@
+use crate::analysis::eval::{clamp_score, white_scores};
+use hubble_db::models::game::Game;
+use hubble_db::models::game_filter::{Color, GameFilter};
+use hubble_db::{Database, DbError};
+use serde::Serialize;
+use shakmaty::{fen, san::San, uci::Uci, Chess, Position};
+use std::cmp::Reverse;
+use std::collections::HashMap;
+
+#[derive(Debug, Serialize, Clone)]
+pub struct RepertoireMove {
+    pub uci: String,
+    pub san: String,
+    pub games: u32,
+    pub won: u32,
+    pub drawn: u32,
+    pub lost: u32,
+    pub avg_opponent_rating: Option<f64>,
+    pub avg_eval: Option<f64>, //Centipawns after the move, from the player's point of view
+    #[serde(skip)]
+    rating_sum: i64,
+    #[serde(skip)]
+    rating_count: u32,
+    #[serde(skip)]
+    eval_sum: i64,
+    #[serde(skip)]
+    eval_count: u32,
+}
+
+impl RepertoireMove {
+    fn new(uci: String, san: String) -> Self {
+        Self {
+            uci,
+            san,
+            games: 0,
+            won: 0,
+            drawn: 0,
+            lost: 0,
+            avg_opponent_rating: None,
+            avg_eval: None,
+            rating_sum: 0,
+            rating_count: 0,
+            eval_sum: 0,
+            eval_count: 0,
+        }
+    }
+
+    fn finish(&mut self) {
+        if self.rating_count > 0 {
+            self.avg_opponent_rating = Some(self.rating_sum as f64 / self.rating_count as f64);
+        }
+        if self.eval_count > 0 {
+            self.avg_eval = Some(self.eval_sum as f64 / self.eval_count as f64);
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct Repertoire {
+    pub player: String,
+    pub color: Color,
+    pub games: u32,
+    pub positions: HashMap<String, Vec<RepertoireMove>>, //Keyed by epd, most played move first
+}
+
+#[derive(Clone, Copy)]
+enum Outcome {
+    Won,
+    Drawn,
+    Lost,
+}
+
+fn player_outcome(game: &Game, player: &str) -> Outcome {
+    match &game.winner {
+        Some(winner) if winner.eq_ignore_ascii_case(player) => Outcome::Won,
+        Some(_) => Outcome::Lost,
+        None => Outcome::Drawn,
+    }
+}
+
+pub fn build_repertoire(games: &[Game], player: &str, color: Color, max_ply: usize) -> Repertoire {
+    let mut positions: HashMap<String, Vec<RepertoireMove>> = HashMap::new();
+    let mut counted = 0;
+
+    for game in games {
+        let is_white = game.white.eq_ignore_ascii_case(player);
+        if is_white != (color == Color::White) {
+            continue;
+        }
+
+        let outcome = player_outcome(game, player);
+        let opponent_rating = if is_white {
+            game.black_rating
+        } else {
+            game.white_rating
+        };
+        let scores = white_scores(game);
+        let mut pos = Chess::default();
+        counted += 1;
+
+        for (idx, mv) in game.moves.iter().take(max_ply).enumerate() {
+            let m = match mv.parse::<Uci>().ok().and_then(|uci| uci.to_move(&pos).ok()) {
+                Some(m) => m,
+                None => break,
+            };
+
+            let entries = positions.entry(fen::epd(&pos)).or_default();
+            let entry = match entries.iter().position(|entry| entry.uci == *mv) {
+                Some(i) => &mut entries[i],
+                None => {
+                    entries.push(RepertoireMove::new(
+                        mv.clone(),
+                        San::from_move(&pos, &m).to_string(),
+                    ));
+                    entries.last_mut().unwrap()
+                }
+            };
+
+            entry.games += 1;
+            match outcome {
+                Outcome::Won => entry.won += 1,
+                Outcome::Drawn => entry.drawn += 1,
+                Outcome::Lost => entry.lost += 1,
+            }
+            if let Some(rating) = opponent_rating {
+                entry.rating_sum += rating as i64;
+                entry.rating_count += 1;
+            }
+            if let Some(score) = scores.get(idx) {
+                let score = if is_white { *score } else { -*score };
+                entry.eval_sum += clamp_score(score) as i64;
+                entry.eval_count += 1;
+            }
+
+            pos.play_unchecked(&m);
+        }
+    }
+
+    for entries in positions.values_mut() {
+        entries.iter_mut().for_each(RepertoireMove::finish);
+        entries.sort_by_key(|entry| Reverse(entry.games));
+    }
+
+    Repertoire {
+        player: player.to_string(),
+        color,
+        games: counted,
+        positions,
+    }
+}
+
+pub async fn player_repertoire(
+    db: &Database,
+    player: &str,
+    color: Color,
+    max_ply: usize,
+    max_games: i64,
+) -> Result<Repertoire, DbError> {
+    let filter = GameFilter {
+        player: Some(player.to_string()),
+        color: Some(color),
+        limit: max_games,
+        ..GameFilter::default()
+    };
+    let page = db.find_games(filter).await?;
+
+    Ok(build_repertoire(&page.games, player, color, max_ply))
+}
@@ -0,0 +1,230 @@
+use hubble_db::models::game::Game;
+use hubble_db::models::game_filter::GameFilter;
+use hubble_db::{Database, DbError};
+use serde::Serialize;
+use std::cmp::Reverse;
+use std::collections::HashMap;
+use std::str::FromStr;
+
+// Two sided 95% confidence
+pub const Z_95: f64 = 1.96;
+
+/// Score expected against an opponent from the Elo formula, 0.5 for equal ratings.
+pub fn expected_score(rating: f64, opponent: f64) -> f64 {
+    1. / (1. + 10f64.powf((opponent - rating) / 400.))
+}
+
+/// Wilson score interval of a score between 0 and 1 over `games` games.
+/// Draws count as half a point, so the score is treated like a proportion of wins.
+pub fn wilson_interval(score: f64, games: u32, z: f64) -> (f64, f64) {
+    if games == 0 {
+        return (0., 1.);
+    }
+
+    let n = games as f64;
+    let z2 = z * z;
+    let center = score + z2 / (2. * n);
+    let margin = z * (score * (1. - score) / n + z2 / (4. * n * n)).sqrt();
+    let denominator = 1. + z2 / n;
+
+    (
+        ((center - margin) / denominator).max(0.),
+        ((center + margin) / denominator).min(1.),
+    )
+}
+
+// A perfect or zero score has no finite performance rating, FIDE caps it this far from the opponents
+pub const PERFORMANCE_CAP: f64 = 800.;
+
+/// Rating whose expected score against the average opponent is the given score,
+/// the inverse of `expected_score`.
+pub fn performance_rating(average_opponent: f64, score: f64) -> f64 {
+    let difference = if score <= 0. {
+        -PERFORMANCE_CAP
+    } else if score >= 1. {
+        PERFORMANCE_CAP
+    } else {
+        (400. * (score / (1. - score)).log10()).clamp(-PERFORMANCE_CAP, PERFORMANCE_CAP)
+    };
+    average_opponent + difference
+}
+
+#[derive(Debug, Serialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum PerformanceGroup {
+    Opening,
+    Color,
+    TimeControl,
+    Month,
+}
+
+impl FromStr for PerformanceGroup {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "opening" => Ok(PerformanceGroup::Opening),
+            "color" => Ok(PerformanceGroup::Color),
+            "time_control" => Ok(PerformanceGroup::TimeControl),
+            "month" => Ok(PerformanceGroup::Month),
+            _ => Err(()),
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Clone, PartialEq)]
+pub struct Performance {
+    pub group: String,
+    pub games: u32,
+    pub won: u32,
+    pub drawn: u32,
+    pub lost: u32,
+    pub score: f64,
+    pub rated_games: u32, //Games where both ratings are known, the rest is left out below
+    pub rated_score: Option<f64>,
+    pub expected_score: Option<f64>,
+    pub average_opponent: Option<f64>,
+    pub performance: Option<f64>,
+}
+
+#[derive(Default)]
+struct Tally {
+    won: u32,
+    drawn: u32,
+    lost: u32,
+    rated: u32,
+    rated_points: f64,
+    expected: f64,
+    opponents: f64,
+}
+
+fn group_key(
+    game: &Game,
+    is_white: bool,
+    group: PerformanceGroup,
+    openings: &HashMap<i32, String>,
+) -> String {
+    let key = match group {
+        PerformanceGroup::Opening => game
+            .book_opening_id
+            .and_then(|id| openings.get(&id))
+            .cloned(),
+        PerformanceGroup::Color => Some(String::from(if is_white { "white" } else { "black" })),
+        PerformanceGroup::TimeControl => game.time_control.clone().or_else(|| game.speed.clone()),
+        PerformanceGroup::Month => game
+            .played_at
+            .map(|played_at| played_at.format("%Y-%m").to_string()),
+    };
+    key.unwrap_or_else(|| String::from("unknown"))
+}
+
+/// Results and performance rating of the player's games, grouped by opening, color,
+/// time control or month. `openings` names the stored book openings by id.
+pub fn player_performance(
+    games: &[Game],
+    player: &str,
+    group: PerformanceGroup,
+    openings: &HashMap<i32, String>,
+) -> Vec<Performance> {
+    let mut tallies: HashMap<String, Tally> = HashMap::new();
+
+    for game in games {
+        let is_white = game.white.eq_ignore_ascii_case(player);
+        if !is_white && !game.black.eq_ignore_ascii_case(player) {
+            continue;
+        }
+
+        let tally = tallies
+            .entry(group_key(game, is_white, group, openings))
+            .or_default();
+        let points = match &game.winner {
+            Some(winner) if winner.eq_ignore_ascii_case(player) => {
+                tally.won += 1;
+                1.
+            }
+            Some(_) => {
+                tally.lost += 1;
+                0.
+            }
+            None => {
+                tally.drawn += 1;
+                0.5
+            }
+        };
+
+        let (rating, opponent) = if is_white {
+            (game.white_rating, game.black_rating)
+        } else {
+            (game.black_rating, game.white_rating)
+        };
+        if let (Some(rating), Some(opponent)) = (rating, opponent) {
+            tally.rated += 1;
+            tally.rated_points += points;
+            tally.expected += expected_score(rating as f64, opponent as f64);
+            tally.opponents += opponent as f64;
+        }
+    }
+
+    let mut performances = tallies
+        .into_iter()
+        .map(|(group, tally)| {
+            let games = tally.won + tally.drawn + tally.lost;
+            let rated = tally.rated as f64;
+            let (rated_score, expected_score, average_opponent, performance) = if tally.rated > 0 {
+                let rated_score = tally.rated_points / rated;
+                let average_opponent = tally.opponents / rated;
+                (
+                    Some(rated_score),
+                    Some(tally.expected / rated),
+                    Some(average_opponent),
+                    Some(performance_rating(average_opponent, rated_score)),
+                )
+            } else {
+                (None, None, None, None)
+            };
+
+            Performance {
+                group,
+                games,
+                won: tally.won,
+                drawn: tally.drawn,
+                lost: tally.lost,
+                score: (tally.won as f64 + tally.drawn as f64 / 2.) / games as f64,
+                rated_games: tally.rated,
+                rated_score,
+                expected_score,
+                average_opponent,
+                performance,
+            }
+        })
+        .collect::<Vec<_>>();
+
+    //Months read best in order, the other groups most played first
+    match group {
+        PerformanceGroup::Month => performances.sort_by(|a, b| a.group.cmp(&b.group)),
+        _ => performances.sort_by_key(|performance| Reverse(performance.games)),
+    }
+    performances
+}
+
+pub async fn stored_performance(
+    db: &Database,
+    player: &str,
+    group: PerformanceGroup,
+    max_games: i64,
+) -> Result<Vec<Performance>, DbError> {
+    let filter = GameFilter {
+        player: Some(player.to_string()),
+        limit: max_games,
+        ..GameFilter::default()
+    };
+    let page = db.find_games(filter).await?;
+    let openings = db
+        .get_all_openings()
+        .await?
+        .into_iter()
+        .map(|opening| (opening.id, opening.name))
+        .collect::<HashMap<_, _>>();
+
+    Ok(player_performance(&page.games, player, group, &openings))
+}
@@ -1,2 +1,4 @@
 pub mod analysis;
 pub mod lichess;
+pub mod pgn;
+pub mod training;
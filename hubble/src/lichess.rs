@@ -1,14 +1,18 @@
+use crate::analysis::blunder::index_blunders;
 use crate::analysis::opening_tree::{MoveEntry, OpeningTree};
-use crate::analysis::GameAnalyser;
+use crate::analysis::{current_profile, GameAnalyser, OpeningClassifier};
+use crate::pgn::game_to_pgn;
 
 use futures_util::StreamExt;
 use pgn_reader::{AsyncBufferedReader, BufferedReader};
+use shakmaty::Chess;
 use std::collections::HashMap;
+use std::sync::Arc;
 
 use regex::Regex;
 
-use hubble_db::models::game::{get_game, save_game, save_games, Game};
-use hubble_db::PgPooledConnection;
+use hubble_db::models::game::Game;
+use hubble_db::{Database, DbError};
 
 const API_BASE: &str = "https://lichess.org";
 
@@ -30,14 +34,22 @@ pub enum AnalysisErrors {
     Lichess,
     Pgn,
     NotFound,
+    Database(DbError),
 }
 
 pub async fn analyse_lichess_game(
-    conn: PgPooledConnection,
+    db: &Database,
+    classifier: &Arc<OpeningClassifier>,
     game_id: &str,
 ) -> Result<Game, AnalysisErrors> {
-    if let Some(game) = get_game(game_id, &conn) {
-        return Ok(game);
+    let stored = db
+        .get_game(game_id)
+        .await
+        .map_err(AnalysisErrors::Database)?;
+    if let Some(game) = stored {
+        if !game.analysis.is_stale(&current_profile()) {
+            return Ok(game);
+        }
     }
     if let Ok(pgn) = get_game_lichess(game_id).await {
         if pgn.contains("<!DOCTYPE html>") {
@@ -45,21 +57,32 @@ pub async fn analyse_lichess_game(
         }
 
         let mut reader = AsyncBufferedReader::new_cursor(&pgn[..]);
-        let mut analyser = GameAnalyser::new().await;
+        let mut analyser = GameAnalyser::new(classifier.clone()).await;
 
         if reader.read_game(&mut analyser).await.is_err() {
             return Err(AnalysisErrors::Pgn);
         }
 
-        match save_game(analyser.game, &conn) {
-            Ok(g) => Ok(g),
-            Err(_) => Err(AnalysisErrors::Lichess),
-        }
+        let game = db
+            .save_game(analyser.game)
+            .await
+            .map_err(AnalysisErrors::Database)?;
+        index_blunders(db, std::slice::from_ref(&game))
+            .await
+            .map_err(AnalysisErrors::Database)?;
+        Ok(game)
     } else {
         Err(AnalysisErrors::Lichess)
     }
 }
 
+async fn store_games(db: &Database, games: Vec<Game>) -> Result<Vec<Game>, DbError> {
+    //The returned games hold the analysis that was kept, their blunders are the ones to index
+    let stored = db.save_games(games).await?;
+    index_blunders(db, &stored).await?;
+    Ok(stored)
+}
+
 pub async fn opening_player(
     username: &str,
 ) -> Result<HashMap<String, Vec<MoveEntry>>, AnalysisErrors> {
@@ -76,27 +99,35 @@ pub async fn opening_player(
             }
         }
 
-        Ok(opening.move_stat)
+        Ok(opening.export(&Chess::default(), usize::MAX))
     } else {
         Err(AnalysisErrors::NotFound)
     }
 }
 
-async fn analyse_games(pgns: String, analyser: &mut GameAnalyser) -> Vec<Game> {
+async fn analyse_games(
+    pgns: String,
+    analyser: &mut GameAnalyser,
+) -> Result<Vec<Game>, AnalysisErrors> {
     let mut reader = AsyncBufferedReader::new_cursor(&pgns[..]);
     let mut matches: Vec<Game> = Vec::new();
 
-    while let Some(_ok) = reader.read_game(analyser).await.unwrap() {
+    while let Some(_ok) = reader
+        .read_game(analyser)
+        .await
+        .map_err(|_| AnalysisErrors::Pgn)?
+    {
         //game over
         let game = analyser.game.clone();
         matches.push(game);
     }
 
-    matches
+    Ok(matches)
 }
 
 pub async fn analyse_player(
-    conn: PgPooledConnection,
+    db: &Database,
+    classifier: &Arc<OpeningClassifier>,
     player_id: &str,
     num_games: usize
 ) -> Result<Vec<Game>, AnalysisErrors> {
@@ -104,8 +135,15 @@ pub async fn analyse_player(
         "{}/api/games/user/{}?max={}&clocks=false&evals=false",
         API_BASE, player_id, num_games
     );
-    let resp = reqwest::get(url).await.unwrap();
+    let resp = reqwest::get(url)
+        .await
+        .map_err(|_| AnalysisErrors::Lichess)?;
     println!("status code {}", resp.status());
+    if resp.status() == reqwest::StatusCode::NOT_FOUND {
+        return Err(AnalysisErrors::NotFound);
+    } else if !resp.status().is_success() {
+        return Err(AnalysisErrors::Lichess);
+    }
     let mut stream = resp.bytes_stream();
 
     let mut pgns = String::from("");
@@ -115,28 +153,31 @@ pub async fn analyse_player(
     let mut all_games: Vec<Game> = Vec::new();
 
     while let Some(Ok(chunk)) = stream.next().await {
-        let pgn = std::str::from_utf8(&chunk).unwrap();
+        let pgn = std::str::from_utf8(&chunk).map_err(|_| AnalysisErrors::Pgn)?;
         if let Some(mat) = re.find(pgn) {
             let url = mat.as_str();
             if let Some(id) = url.split('/').nth(1) {
                 println!("id {}", id);
-                if let Some(game) = get_game(id, &conn) {
-                    println!("Game already analysed");
-                    all_games.push(game);
-                    continue;
+                let stored = db.get_game(id).await.map_err(AnalysisErrors::Database)?;
+                if let Some(game) = stored {
+                    if !game.analysis.is_stale(&current_profile()) {
+                        println!("Game already analysed");
+                        all_games.push(game);
+                        continue;
+                    }
                 }
             }
         }
 
         pgns.push_str(&format!("{}\n", pgn));
 
-        let mut analyser = GameAnalyser::new().await;
-        let games = analyse_games(pgns, &mut analyser).await;
-        match save_games(games, &conn) {
+        let mut analyser = GameAnalyser::new(classifier.clone()).await;
+        let games = analyse_games(pgns, &mut analyser).await?;
+        match store_games(db, games).await {
             Ok(mut gs) => all_games.append(&mut gs),
             Err(e) => {
                 println!("{}", e);
-                return Err(AnalysisErrors::Lichess);
+                return Err(AnalysisErrors::Database(e));
             }
         }
         pgns = String::from("");
@@ -144,3 +185,69 @@ pub async fn analyse_player(
 
     Ok(all_games)
 }
+
+pub async fn reanalyse_stale_games(
+    db: &Database,
+    classifier: &Arc<OpeningClassifier>,
+    player_id: Option<&str>,
+    batch_size: i64,
+) -> Result<Vec<Game>, AnalysisErrors> {
+    //Re-runs the analyser on games stored with an older analysis profile, one batch at a time.
+    //Games that can't be rebuilt or analysed are logged and left out of the later batches.
+    let profile = current_profile();
+    let mut analyser = GameAnalyser::new(classifier.clone()).await;
+    let mut all_games: Vec<Game> = Vec::new();
+    let mut skipped: Vec<String> = Vec::new();
+
+    loop {
+        let stale = db
+            .get_stale_games(
+                profile.clone(),
+                player_id.map(str::to_string),
+                skipped.clone(),
+                batch_size,
+            )
+            .await
+            .map_err(AnalysisErrors::Database)?;
+        if stale.is_empty() {
+            break;
+        }
+
+        let mut pgns = String::from("");
+        for game in &stale {
+            match game_to_pgn(game) {
+                Some(pgn) => pgns.push_str(&pgn),
+                None => {
+                    //Games from a custom position are stored without it
+                    println!("Could not rebuild pgn for {}, skipping it", game.id);
+                    skipped.push(game.id.clone());
+                }
+            }
+        }
+
+        //Games left out of the batch by a read error are skipped below
+        let games = match analyse_games(pgns, &mut analyser).await {
+            Ok(games) => games,
+            Err(e) => {
+                println!("Could not read the batch: {:?}", e);
+                Vec::new()
+            }
+        };
+        println!("Re-analysed {} games", games.len());
+        for game in &stale {
+            if !skipped.contains(&game.id) && !games.iter().any(|g| g.id == game.id) {
+                println!("Could not re-analyse {}, skipping it", game.id);
+                skipped.push(game.id.clone());
+            }
+        }
+        match store_games(db, games).await {
+            Ok(mut gs) => all_games.append(&mut gs),
+            Err(e) => {
+                println!("{}", e);
+                return Err(AnalysisErrors::Database(e));
+            }
+        }
+    }
+
+    Ok(all_games)
+}
@@ -0,0 +1,204 @@
+use crate::analysis::repertoire::{Repertoire, RepertoireMove};
+use hubble_db::models::game::Game;
+use hubble_db::models::game_filter::Color;
+use hubble_db::models::puzzle::Puzzle;
+use shakmaty::fen::{self, Fen};
+use shakmaty::{san::San, uci::Uci, CastlingMode, Chess, Position};
+
+fn result_string(game: &Game) -> &'static str {
+    match &game.winner {
+        Some(winner) if *winner == game.white => "1-0",
+        Some(winner) if *winner == game.black => "0-1",
+        _ => "1/2-1/2",
+    }
+}
+
+fn format_header(key: &str, value: &str) -> String {
+    format!("[{} \"{}\"]\n", key, value.replace('"', "'"))
+}
+
+pub fn game_to_pgn(game: &Game) -> Option<String> {
+    //Rebuilds a pgn from a stored game so it can be fed to the visitors again
+    let result = result_string(game);
+    let mut pgn = String::new();
+
+    pgn.push_str(&format_header("Site", &format!("https://lichess.org/{}", game.id)));
+    pgn.push_str(&format_header("White", &game.white));
+    pgn.push_str(&format_header("Black", &game.black));
+    if let Some(rating) = game.white_rating {
+        pgn.push_str(&format_header("WhiteElo", &rating.to_string()));
+    }
+    if let Some(rating) = game.black_rating {
+        pgn.push_str(&format_header("BlackElo", &rating.to_string()));
+    }
+    if let Some(eco) = &game.opening_id {
+        pgn.push_str(&format_header("ECO", eco));
+    }
+    if let Some(played_at) = game.played_at {
+        pgn.push_str(&format_header("UTCDate", &played_at.format("%Y.%m.%d").to_string()));
+        pgn.push_str(&format_header("UTCTime", &played_at.format("%H:%M:%S").to_string()));
+    }
+    if let Some(time_control) = &game.time_control {
+        pgn.push_str(&format_header("TimeControl", time_control));
+    }
+    pgn.push_str(&format_header("Result", result));
+    pgn.push('\n');
+
+    let mut pos = Chess::default();
+    for (idx, mv) in game.moves.iter().enumerate() {
+        let uci = mv.parse::<Uci>().ok()?;
+        let m = uci.to_move(&pos).ok()?;
+        if idx % 2 == 0 {
+            pgn.push_str(&format!("{}. ", idx / 2 + 1));
+        }
+        pgn.push_str(&format!("{} ", San::from_move(&pos, &m)));
+        pos.play_unchecked(&m);
+    }
+
+    pgn.push_str(result);
+    pgn.push_str("\n\n");
+    Some(pgn)
+}
+
+fn move_prefix(ply: usize, force: bool) -> String {
+    //ply is the number of half moves already played
+    if ply % 2 == 0 {
+        format!("{}. ", ply / 2 + 1)
+    } else if force {
+        format!("{}... ", ply / 2 + 1)
+    } else {
+        String::new()
+    }
+}
+
+fn repertoire_comment(mv: &RepertoireMove) -> String {
+    let mut comment = format!(
+        "{{{} games: +{} ={} -{}",
+        mv.games, mv.won, mv.drawn, mv.lost
+    );
+    if let Some(eval) = mv.avg_eval {
+        comment.push_str(&format!(", avg eval {:+.2}", eval / 100.));
+    }
+    comment.push('}');
+    comment
+}
+
+fn write_repertoire_line(
+    repertoire: &Repertoire,
+    pos: &Chess,
+    ply: usize,
+    min_games: u32,
+    max_ply: usize,
+    pgn: &mut String,
+) {
+    if ply >= max_ply {
+        return;
+    }
+
+    let moves = match repertoire.positions.get(&fen::epd(pos)) {
+        Some(moves) => moves
+            .iter()
+            .filter(|mv| mv.games >= min_games)
+            .collect::<Vec<_>>(),
+        None => return,
+    };
+
+    let mut continuations = Vec::new();
+    for mv in &moves {
+        if let Some(m) = mv.uci.parse::<Uci>().ok().and_then(|uci| uci.to_move(pos).ok()) {
+            let mut next = pos.clone();
+            next.play_unchecked(&m);
+            continuations.push((*mv, next));
+        }
+    }
+
+    let (main, main_pos) = match continuations.first() {
+        Some(first) => first,
+        None => return,
+    };
+
+    //Every move has a comment, so black moves always need their number
+    pgn.push_str(&move_prefix(ply, true));
+    pgn.push_str(&format!("{} {} ", main.san, repertoire_comment(main)));
+
+    for (alt, alt_pos) in continuations.iter().skip(1) {
+        pgn.push_str("( ");
+        pgn.push_str(&move_prefix(ply, true));
+        pgn.push_str(&format!("{} {} ", alt.san, repertoire_comment(alt)));
+        write_repertoire_line(repertoire, alt_pos, ply + 1, min_games, max_ply, pgn);
+        pgn.push_str(") ");
+    }
+
+    write_repertoire_line(repertoire, main_pos, ply + 1, min_games, max_ply, pgn);
+}
+
+pub fn repertoire_to_pgn(repertoire: &Repertoire, min_games: u32, max_ply: usize) -> String {
+    //Most played move is the mainline, the rest become variations
+    let color = match repertoire.color {
+        Color::White => "white",
+        Color::Black => "black",
+    };
+    let mut pgn = String::new();
+
+    pgn.push_str(&format_header(
+        "Event",
+        &format!("Repertoire of {} as {}", repertoire.player, color),
+    ));
+    pgn.push_str(&format_header("Site", "Hubble"));
+    pgn.push_str(&format_header("Annotator", "Hubble"));
+    pgn.push_str(&format_header("Result", "*"));
+    pgn.push('\n');
+
+    pgn.push_str(&format!(
+        "{{{} games, evals are from the point of view of {}}} ",
+        repertoire.games, color
+    ));
+    write_repertoire_line(repertoire, &Chess::default(), 0, min_games, max_ply, &mut pgn);
+
+    pgn.push_str("*\n\n");
+    pgn
+}
+
+fn puzzle_to_pgn(puzzle: &Puzzle) -> Option<String> {
+    let pos: Chess = puzzle
+        .fen
+        .parse::<Fen>()
+        .ok()?
+        .position(CastlingMode::Standard)
+        .ok()?;
+    let mut pgn = String::new();
+
+    pgn.push_str(&format_header(
+        "Event",
+        &format!("Puzzle of {}", puzzle.player),
+    ));
+    pgn.push_str(&format_header(
+        "Site",
+        &format!("https://lichess.org/{}#{}", puzzle.game_id, puzzle.ply),
+    ));
+    pgn.push_str(&format_header("Annotator", "Hubble"));
+    pgn.push_str(&format_header("SetUp", "1"));
+    pgn.push_str(&format_header("FEN", &puzzle.fen));
+    pgn.push_str(&format_header("Result", "*"));
+    pgn.push('\n');
+
+    let played = puzzle.played.parse::<Uci>().ok()?.to_move(&pos).ok()?;
+    let ply = puzzle.ply as usize;
+    let mut line = pos.clone();
+    for (idx, uci) in puzzle.solution.iter().enumerate() {
+        let m = uci.parse::<Uci>().ok()?.to_move(&line).ok()?;
+        pgn.push_str(&move_prefix(ply + idx, idx == 0));
+        pgn.push_str(&format!("{} ", San::from_move(&line, &m)));
+        line.play_unchecked(&m);
+    }
+    pgn.push_str(&format!(
+        "{{{} was played in the game}} *\n\n",
+        San::from_move(&pos, &played)
+    ));
+    Some(pgn)
+}
+
+/// Puzzles as pgn games starting from the puzzle position, the solution as mainline.
+pub fn puzzles_to_pgn(puzzles: &[Puzzle]) -> String {
+    puzzles.iter().filter_map(puzzle_to_pgn).collect()
+}
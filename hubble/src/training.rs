@@ -0,0 +1,288 @@
+use crate::analysis::repertoire::{player_repertoire, Repertoire};
+use chrono::{Duration, NaiveDateTime, Utc};
+use hubble_db::models::game_filter::Color;
+use hubble_db::models::player_key;
+use hubble_db::models::puzzle::Puzzle;
+use hubble_db::models::training::{
+    NewTrainingAttempt, NewTrainingItem, Schedule, TrainingItem, TrainingKind,
+};
+use hubble_db::{Database, DbError};
+use serde::Serialize;
+use shakmaty::fen::Fen;
+use shakmaty::san::SanPlus;
+use shakmaty::{uci::Uci, CastlingMode, Chess, Setup};
+use std::collections::HashMap;
+
+// SM-2 never lets an item get easier to forget than this
+pub const MIN_EASE: f64 = 1.3;
+// Right answers slower than these were harder to recall
+const QUICK_ANSWER_MS: i32 = 10_000;
+const SLOW_ANSWER_MS: i32 = 30_000;
+
+#[derive(Debug)]
+pub enum TrainingError {
+    NotFound,
+    InvalidPosition,
+    IllegalMove,
+    Database(DbError),
+}
+
+impl From<DbError> for TrainingError {
+    fn from(e: DbError) -> Self {
+        TrainingError::Database(e)
+    }
+}
+
+/// Which positions of the player's own games become training items.
+pub struct TrainingOptions {
+    pub max_ply: usize, //Repertoire positions this deep at most
+    pub max_games: i64, //Latest games per color the repertoire is built from
+    pub min_games: u32, //A repertoire move has to be this common to count as an answer
+}
+
+impl Default for TrainingOptions {
+    fn default() -> Self {
+        Self {
+            max_ply: 16,
+            max_games: 1000,
+            min_games: 3,
+        }
+    }
+}
+
+/// SM-2 recall quality from 0 to 5. A wrong answer is a failed recall,
+/// right ones are graded by how long they took.
+pub fn recall_quality(correct: bool, time_ms: i32) -> u8 {
+    if !correct {
+        1
+    } else if time_ms <= QUICK_ANSWER_MS {
+        5
+    } else if time_ms <= SLOW_ANSWER_MS {
+        4
+    } else {
+        3
+    }
+}
+
+/// Schedule after an answer of the given quality, following SM-2: failed items start over
+/// the next day, recalled ones come back after 1 day, 6 days, then the last interval
+/// times the ease.
+pub fn review(schedule: &Schedule, quality: u8, now: NaiveDateTime) -> Schedule {
+    let (repetitions, interval_days) = if quality < 3 {
+        (0, 1)
+    } else {
+        let interval_days = match schedule.repetitions {
+            0 => 1,
+            1 => 6,
+            _ => (schedule.interval_days as f64 * schedule.ease).round() as i32,
+        };
+        (schedule.repetitions + 1, interval_days)
+    };
+
+    let miss = 5. - quality.min(5) as f64;
+    let ease = (schedule.ease + 0.1 - miss * (0.08 + miss * 0.02)).max(MIN_EASE);
+
+    Schedule {
+        repetitions,
+        interval_days,
+        ease,
+        due_at: now + Duration::days(interval_days as i64),
+    }
+}
+
+/// Reads a move in uci or san and returns it in uci, none when it is not a legal move.
+pub fn parse_move(pos: &Chess, submitted: &str) -> Option<String> {
+    let submitted = submitted.trim();
+    let m = submitted
+        .parse::<Uci>()
+        .ok()
+        .and_then(|uci| uci.to_move(pos).ok())
+        .or_else(|| {
+            submitted
+                .parse::<SanPlus>()
+                .ok()
+                .and_then(|san| san.san.to_move(pos).ok())
+        })?;
+    Some(m.to_uci(CastlingMode::Standard).to_string())
+}
+
+/// The submitted move in uci and whether it is one of the item's answers.
+pub fn check_answer(item: &TrainingItem, submitted: &str) -> Result<(String, bool), TrainingError> {
+    let pos: Chess = item
+        .fen
+        .parse::<Fen>()
+        .ok()
+        .and_then(|fen| fen.position(CastlingMode::Standard).ok())
+        .ok_or(TrainingError::InvalidPosition)?;
+    let uci = parse_move(&pos, submitted).ok_or(TrainingError::IllegalMove)?;
+    let correct = item.answers.contains(&uci);
+    Ok((uci, correct))
+}
+
+/// Positions of the repertoire where the player is to move, answered by every move
+/// they played there at least `min_games` times.
+pub fn repertoire_items(repertoire: &Repertoire, min_games: u32) -> Vec<NewTrainingItem> {
+    let turn = match repertoire.color {
+        Color::White => shakmaty::Color::White,
+        Color::Black => shakmaty::Color::Black,
+    };
+
+    let mut items = repertoire
+        .positions
+        .iter()
+        .filter(|(epd, _)| epd.parse::<Fen>().ok().map(|fen| fen.turn()) == Some(turn))
+        .filter_map(|(epd, moves)| {
+            let answers = moves
+                .iter()
+                .filter(|mv| mv.games >= min_games)
+                .map(|mv| mv.uci.clone())
+                .collect::<Vec<_>>();
+            if answers.is_empty() {
+                return None;
+            }
+            Some(NewTrainingItem {
+                player: player_key(&repertoire.player),
+                kind: TrainingKind::Repertoire.as_str().to_string(),
+                fen: epd.clone(),
+                answers,
+                game_id: None,
+            })
+        })
+        .collect::<Vec<_>>();
+    items.sort_by(|a, b| a.fen.cmp(&b.fen));
+    items
+}
+
+/// Training items of the player's puzzles, the first move of the solution is the answer.
+pub fn puzzle_items(player: &str, puzzles: Vec<Puzzle>) -> Vec<NewTrainingItem> {
+    puzzles
+        .into_iter()
+        .filter(|puzzle| !puzzle.solution.is_empty())
+        .map(|puzzle| NewTrainingItem {
+            player: player_key(player),
+            kind: TrainingKind::Blunder.as_str().to_string(),
+            fen: puzzle.fen,
+            answers: puzzle.solution[..1].to_vec(),
+            game_id: Some(puzzle.game_id),
+        })
+        .collect()
+}
+
+/// One item per kind and position, as they are stored. The same blunder in several games
+/// gives a single item answered by any of their solutions, linked to the first game.
+pub fn merge_items(items: Vec<NewTrainingItem>) -> Vec<NewTrainingItem> {
+    let mut merged: Vec<NewTrainingItem> = Vec::new();
+    let mut index: HashMap<(String, String), usize> = HashMap::new();
+
+    for item in items {
+        match index.get(&(item.kind.clone(), item.fen.clone())) {
+            Some(&i) => {
+                let existing = &mut merged[i];
+                for answer in item.answers {
+                    if !existing.answers.contains(&answer) {
+                        existing.answers.push(answer);
+                    }
+                }
+            }
+            None => {
+                index.insert((item.kind.clone(), item.fen.clone()), merged.len());
+                merged.push(item);
+            }
+        }
+    }
+
+    merged
+}
+
+/// Adds the player's stored puzzles and repertoire positions as training items.
+/// Items already scheduled keep their schedule.
+pub async fn sync_training_items(
+    db: &Database,
+    player: &str,
+    options: TrainingOptions,
+) -> Result<usize, DbError> {
+    let mut items = puzzle_items(player, db.get_puzzles(player).await?);
+
+    for color in [Color::White, Color::Black] {
+        let repertoire =
+            player_repertoire(db, player, color, options.max_ply, options.max_games).await?;
+        items.extend(repertoire_items(&repertoire, options.min_games));
+    }
+
+    db.upsert_training_items(merge_items(items)).await
+}
+
+/// What the player sees of a due item, without its answers.
+#[derive(Debug, Serialize)]
+pub struct TrainingPosition {
+    pub id: i32,
+    pub kind: String,
+    pub fen: String,
+    pub game_id: Option<String>,
+    pub repetitions: i32,
+    pub due_at: NaiveDateTime,
+}
+
+impl From<TrainingItem> for TrainingPosition {
+    fn from(item: TrainingItem) -> Self {
+        Self {
+            id: item.id,
+            kind: item.kind,
+            fen: item.fen,
+            game_id: item.game_id,
+            repetitions: item.repetitions,
+            due_at: item.due_at,
+        }
+    }
+}
+
+pub async fn next_position(
+    db: &Database,
+    player: &str,
+    kind: Option<TrainingKind>,
+) -> Result<Option<TrainingPosition>, DbError> {
+    let item = db
+        .next_due_item(player, kind, Utc::now().naive_utc())
+        .await?;
+    Ok(item.map(TrainingPosition::from))
+}
+
+#[derive(Debug, Serialize)]
+pub struct AttemptResult {
+    pub submitted: String, //In uci
+    pub correct: bool,
+    pub answers: Vec<String>,
+    pub schedule: Schedule,
+}
+
+/// Checks the move against the item, stores the attempt and reschedules the item.
+pub async fn submit_attempt(
+    db: &Database,
+    item_id: i32,
+    submitted: &str,
+    time_ms: i32,
+) -> Result<AttemptResult, TrainingError> {
+    let item = db
+        .get_training_item(item_id)
+        .await?
+        .ok_or(TrainingError::NotFound)?;
+    let (uci, correct) = check_answer(&item, submitted)?;
+
+    let now = Utc::now().naive_utc();
+    let schedule = review(&item.schedule(), recall_quality(correct, time_ms), now);
+    let attempt = NewTrainingAttempt {
+        item_id,
+        submitted: uci.clone(),
+        correct,
+        time_ms,
+        attempted_at: now,
+    };
+    let item = db.record_attempt(attempt, schedule).await?;
+
+    Ok(AttemptResult {
+        submitted: uci,
+        correct,
+        answers: item.answers.clone(),
+        schedule: item.schedule(),
+    })
+}
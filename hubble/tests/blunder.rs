@@ -0,0 +1,40 @@
+mod common;
+
+use common::GameBuilder;
+use hubble::analysis::blunder::blunder_rows;
+
+#[test]
+fn rows_name_the_moved_piece() {
+    let game = GameBuilder::new()
+        .id("abcdefgh")
+        .moves("e2e4 e7e5 g1f3 b8c6 f1c4 g8f6 e1g1")
+        .blunders(&[2, 6], &[5], &[])
+        .build();
+
+    let rows = blunder_rows(&game);
+    let rows = rows
+        .iter()
+        .map(|row| (row.ply, row.san.as_str(), row.piece.as_str()))
+        .collect::<Vec<_>>();
+
+    assert_eq!(
+        rows,
+        vec![
+            (2, "Nf3", "knight"),
+            (5, "Nf6", "knight"),
+            (6, "O-O", "king")
+        ]
+    );
+}
+
+#[test]
+fn stops_at_unreadable_moves() {
+    let game = GameBuilder::new()
+        .moves("e2e4 e2e4 d7d5")
+        .blunders(&[], &[], &[0, 2])
+        .build();
+
+    let rows = blunder_rows(&game);
+    assert_eq!(rows.len(), 1);
+    assert_eq!(rows[0].piece, "pawn");
+}
@@ -0,0 +1,58 @@
+mod common;
+
+use common::{line, GameBuilder};
+use hubble::analysis::book_exit::{book_exit, BookExit, BOOK_EXIT_WINDOW};
+use hubble_db::models::game::Game;
+
+fn game(left_book_ply: i32) -> Game {
+    GameBuilder::new()
+        .moves("e2e4 e7e5 g1f3 b8c6 f1b5 a7a6 b5a4 g8f6 e1g1 f8e7")
+        //Side to move scores, odd indexes are white to move
+        .scores(&[
+            "-30", "25", "-20", "20", "-10", "40", "-60", "90", "150", "300",
+        ])
+        .left_book_ply(left_book_ply)
+        .build()
+}
+
+#[test]
+fn white_leaving_book() {
+    let exit = book_exit(&game(2), BOOK_EXIT_WINDOW).unwrap();
+
+    assert_eq!(
+        exit,
+        BookExit {
+            ply: 2,
+            by_white: true,
+            swing: -175,
+        }
+    );
+}
+
+#[test]
+fn black_leaving_book() {
+    let exit = book_exit(&game(3), BOOK_EXIT_WINDOW).unwrap();
+
+    assert_eq!(
+        exit,
+        BookExit {
+            ply: 3,
+            by_white: false,
+            swing: -280,
+        }
+    );
+}
+
+#[test]
+fn game_that_never_left_book() {
+    assert!(book_exit(&game(10), BOOK_EXIT_WINDOW).is_none());
+}
+
+#[test]
+fn game_from_a_custom_position() {
+    //Black to move first, the moves don't replay from the standard start
+    let mut game = game(3);
+    game.moves = line("e8e7 e1e2 h8h1 a1b1 e7e6 e2e3 h1h3 e3e4 h3h4 e4e5");
+
+    assert!(book_exit(&game, BOOK_EXIT_WINDOW).is_none());
+}
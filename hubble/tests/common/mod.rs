@@ -0,0 +1,91 @@
+//Every test crate only uses part of these helpers
+#![allow(dead_code)]
+
+use chrono::NaiveDate;
+use hubble_db::models::game::Game;
+use shakmaty::fen::Fen;
+use shakmaty::{CastlingMode, Chess};
+
+pub fn position(fen: &str) -> Chess {
+    fen.parse::<Fen>()
+        .unwrap()
+        .position(CastlingMode::Standard)
+        .unwrap()
+}
+
+pub fn line(moves: &str) -> Vec<String> {
+    moves.split_whitespace().map(str::to_string).collect()
+}
+
+/// Builds a [`Game`] for the tests, starting from [`Game::empty`].
+pub struct GameBuilder {
+    game: Game,
+}
+
+impl GameBuilder {
+    pub fn new() -> Self {
+        Self {
+            game: Game::empty(),
+        }
+    }
+
+    pub fn id(mut self, id: &str) -> Self {
+        self.game.id = id.to_string();
+        self
+    }
+
+    pub fn players(mut self, white: &str, black: &str) -> Self {
+        self.game.white = white.to_string();
+        self.game.black = black.to_string();
+        self
+    }
+
+    pub fn winner(mut self, winner: Option<&str>) -> Self {
+        self.game.winner = winner.map(str::to_string);
+        self
+    }
+
+    pub fn ratings(mut self, white: Option<i32>, black: Option<i32>) -> Self {
+        self.game.white_rating = white;
+        self.game.black_rating = black;
+        self
+    }
+
+    pub fn played_in(mut self, year: i32, month: u32) -> Self {
+        self.game.played_at =
+            NaiveDate::from_ymd_opt(year, month, 1).and_then(|d| d.and_hms_opt(12, 0, 0));
+        self
+    }
+
+    pub fn moves(mut self, moves: &str) -> Self {
+        self.game.moves = line(moves);
+        self
+    }
+
+    pub fn scores(mut self, scores: &[&str]) -> Self {
+        self.game.scores = scores.iter().map(|score| score.to_string()).collect();
+        self
+    }
+
+    //Flat scores for every move already given
+    pub fn even_scores(mut self) -> Self {
+        self.game.scores = vec!["0".to_string(); self.game.moves.len()];
+        self
+    }
+
+    pub fn blunders(mut self, opening: &[i32], middle_game: &[i32], end_game: &[i32]) -> Self {
+        self.game.blunders.opening = opening.to_vec();
+        self.game.blunders.middle_game = middle_game.to_vec();
+        self.game.blunders.end_game = end_game.to_vec();
+        self
+    }
+
+    pub fn left_book_ply(mut self, ply: i32) -> Self {
+        self.game.left_book_ply = Some(ply);
+        self
+    }
+
+    pub fn build(self) -> Game {
+        self.game
+    }
+}
@@ -0,0 +1,67 @@
+mod common;
+
+use common::{line, position};
+use hubble::analysis::motif::{classify_blunder, Motif};
+use shakmaty::uci::Uci;
+
+fn classify(fen: &str, played: &str, best: &str, refutation: &str) -> Vec<Motif> {
+    let pos = position(fen);
+    let played = played.parse::<Uci>().unwrap().to_move(&pos).unwrap();
+    classify_blunder(&pos, &played, &line(best), &line(refutation))
+}
+
+#[test]
+fn tags_hanging_pieces() {
+    let motifs = classify("4k3/8/8/4p3/8/8/8/3QK3 w - - 0 1", "d1d4", "e1e2", "e5d4");
+
+    assert_eq!(motifs, vec![Motif::HangingPiece]);
+}
+
+#[test]
+fn tags_allowed_back_rank_mate() {
+    let motifs = classify(
+        "r5k1/5ppp/8/8/8/8/5PPP/3R2K1 b - - 0 1",
+        "a8a7",
+        "g8f8",
+        "d1d8",
+    );
+
+    assert_eq!(motifs, vec![Motif::BackRank, Motif::AllowedMate]);
+}
+
+#[test]
+fn tags_missed_mate() {
+    let motifs = classify("6k1/r4ppp/8/8/8/8/5PPP/3R2K1 w - - 0 1", "g1f1", "d1d8", "");
+
+    assert_eq!(motifs, vec![Motif::MissedMate]);
+}
+
+#[test]
+fn tags_forks() {
+    let fen = "r3k3/7p/8/1N6/8/8/8/4K3";
+
+    let allowed = classify(
+        &format!("{} b - - 0 1", fen),
+        "h7h6",
+        "a8a5",
+        "b5c7 e8d7 c7a8",
+    );
+    assert_eq!(allowed, vec![Motif::AllowedFork]);
+
+    let missed = classify(&format!("{} w - - 0 1", fen), "e1e2", "b5c7", "");
+    assert_eq!(missed, vec![Motif::MissedFork]);
+}
+
+#[test]
+fn tags_pins() {
+    let motifs = classify("4k3/3n3p/8/8/8/8/8/4KB2 b - - 0 1", "h7h6", "d7e5", "f1b5");
+
+    assert_eq!(motifs, vec![Motif::Pin]);
+}
+
+#[test]
+fn quiet_blunders_have_no_motif() {
+    let motifs = classify("4k3/3n3p/8/8/8/8/8/4KB2 b - - 0 1", "h7h6", "d7e5", "e1e2");
+
+    assert!(motifs.is_empty());
+}
@@ -0,0 +1,91 @@
+mod common;
+
+use common::line;
+use hubble::analysis::OpeningClassifier;
+use hubble_db::models::Opening;
+
+fn classifier() -> OpeningClassifier {
+    OpeningClassifier::new(vec![
+        Opening::new(
+            1,
+            "C20".to_string(),
+            "King's Pawn Game".to_string(),
+            "1. e4 e5".to_string(),
+        ),
+        Opening::new(
+            2,
+            "C50".to_string(),
+            "Italian Game".to_string(),
+            "1. e4 e5 2. Nf3 Nc6 3. Bc4".to_string(),
+        ),
+    ])
+}
+
+#[test]
+fn classifies_by_deepest_position() {
+    let classifier = classifier();
+    let classification = classifier
+        .classify_uci(&line("e2e4 e7e5 g1f3 b8c6 f1c4 g8f6 d2d3"))
+        .unwrap();
+
+    assert_eq!(classification.opening.name, "Italian Game");
+    assert_eq!(classification.ply, 5);
+}
+
+#[test]
+fn recognises_transpositions() {
+    let classifier = classifier();
+    let classification = classifier
+        .classify_san(&line("Nf3 Nc6 e4 e5 Bc4 Bc5"))
+        .unwrap();
+
+    assert_eq!(classification.opening.name, "Italian Game");
+    assert_eq!(classification.ply, 5);
+}
+
+#[test]
+fn unknown_games_are_not_classified() {
+    let classifier = classifier();
+
+    assert!(classifier.classify_uci(&line("d2d4 d7d5")).is_none());
+}
+
+#[test]
+fn uses_stored_positions() {
+    //A stored position wins over the pgn, which is left unparseable on purpose
+    let mut opening = Opening::new(
+        3,
+        "D00".to_string(),
+        "Queen's Pawn Game".to_string(),
+        "not a pgn".to_string(),
+    );
+    opening.epd = Some("rnbqkbnr/ppp1pppp/8/3p4/3P4/8/PPP1PPPP/RNBQKBNR w KQkq -".to_string());
+    opening.ply = Some(2);
+    let classifier = OpeningClassifier::new(vec![opening]);
+
+    let classification = classifier.classify_uci(&line("d2d4 d7d5 c2c4")).unwrap();
+    assert_eq!(classification.opening.name, "Queen's Pawn Game");
+    assert_eq!(classification.ply, 2);
+}
+
+#[test]
+fn same_positions_keep_the_lowest_id() {
+    //Both lines reach the same position in two plies, whichever is loaded first
+    let opening = |id, name: &str, pgn: &str| {
+        Opening::new(id, "C20".to_string(), name.to_string(), pgn.to_string())
+    };
+    let openings = || {
+        vec![
+            opening(5, "King's Pawn Game", "1. e4 e5"),
+            opening(4, "Open Game", "1. e4 e5"),
+        ]
+    };
+
+    let mut reversed = openings();
+    reversed.reverse();
+    for openings in [openings(), reversed] {
+        let classifier = OpeningClassifier::new(openings);
+        let classification = classifier.classify_uci(&line("e2e4 e7e5")).unwrap();
+        assert_eq!(classification.opening.id, 4);
+    }
+}
@@ -0,0 +1,152 @@
+use hubble::analysis::{group_openings, OpeningClassifier, OpeningCounter, OpeningResult};
+use hubble_db::models::opening_name::{OpeningLevel, OpeningName};
+use hubble_db::models::Opening;
+use pgn_reader::BufferedReader;
+use std::collections::HashMap;
+
+const GAMES: &str = include_str!("fixtures/opening_counter.pgn");
+
+fn openings() -> Vec<Opening> {
+    vec![
+        Opening::new(
+            1,
+            "C50".to_string(),
+            "Italian Game".to_string(),
+            "1. e4 e5 2. Nf3 Nc6 3. Bc4".to_string(),
+        ),
+        Opening::new(
+            2,
+            "C50".to_string(),
+            "Italian Game: Giuoco Piano".to_string(),
+            "1. e4 e5 2. Nf3 Nc6 3. Bc4 Bc5".to_string(),
+        ),
+        Opening::new(
+            3,
+            "B50".to_string(),
+            "Sicilian Defense: Modern Variations".to_string(),
+            "1. e4 c5 2. Nf3 d6".to_string(),
+        ),
+    ]
+}
+
+fn count(white_only: Option<bool>) -> HashMap<String, OpeningResult> {
+    let classifier = OpeningClassifier::new(openings());
+    let mut counter = OpeningCounter::new(&classifier, "alice".to_string(), white_only);
+    let mut reader = BufferedReader::new_cursor(GAMES.as_bytes());
+
+    while reader.read_game(&mut counter).unwrap().is_some() {}
+
+    counter.openings
+}
+
+fn result(won: u32, tie: u32, lost: u32) -> OpeningResult {
+    OpeningResult {
+        won,
+        tie,
+        lost,
+        expected: 0.,
+        rated: 0,
+    }
+}
+
+#[test]
+fn counts_results_for_both_colors() {
+    let openings = count(None);
+
+    assert_eq!(openings.len(), 4);
+    assert_eq!(openings["Italian Game: Giuoco Piano"], result(3, 1, 0));
+    assert_eq!(openings["Italian Game"], result(0, 0, 1));
+    assert_eq!(
+        openings["Sicilian Defense: Modern Variations"],
+        result(0, 0, 1)
+    );
+    assert_eq!(openings["D30"], result(0, 1, 0));
+}
+
+#[test]
+fn counts_only_white_games() {
+    let openings = count(Some(true));
+
+    assert_eq!(openings.len(), 3);
+    assert_eq!(openings["Italian Game: Giuoco Piano"], result(2, 0, 0));
+    assert_eq!(openings["Italian Game"], result(0, 0, 1));
+    assert_eq!(openings["D30"], result(0, 1, 0));
+}
+
+#[test]
+fn counts_only_black_games() {
+    let openings = count(Some(false));
+
+    assert_eq!(openings.len(), 2);
+    assert_eq!(openings["Italian Game: Giuoco Piano"], result(1, 1, 0));
+    assert_eq!(
+        openings["Sicilian Defense: Modern Variations"],
+        result(0, 0, 1)
+    );
+}
+
+#[test]
+fn ignores_games_without_the_player() {
+    let classifier = OpeningClassifier::new(openings());
+    let mut counter = OpeningCounter::new(&classifier, "erin".to_string(), None);
+    let mut reader = BufferedReader::new_cursor(GAMES.as_bytes());
+
+    while reader.read_game(&mut counter).unwrap().is_some() {}
+
+    assert!(counter.openings.is_empty());
+}
+
+#[test]
+fn groups_openings_by_family() {
+    let openings = count(None);
+
+    let families = group_openings(&openings, OpeningLevel::Family);
+    assert_eq!(families.len(), 3);
+    assert_eq!(families["Italian Game"], result(3, 1, 1));
+    assert_eq!(families["Sicilian Defense"], result(0, 0, 1));
+    assert_eq!(families["D30"], result(0, 1, 0));
+
+    assert_eq!(
+        group_openings(&openings, OpeningLevel::SubVariation),
+        openings
+    );
+}
+
+#[test]
+fn parses_opening_names() {
+    let name =
+        OpeningName::parse("Sicilian Defense: Najdorf Variation, English Attack, Anti-English");
+
+    assert_eq!(name.family, "Sicilian Defense");
+    assert_eq!(name.variation.as_deref(), Some("Najdorf Variation"));
+    assert_eq!(
+        name.sub_variation.as_deref(),
+        Some("English Attack, Anti-English")
+    );
+    assert_eq!(name.at(OpeningLevel::Family), "Sicilian Defense");
+    assert_eq!(
+        name.at(OpeningLevel::Variation),
+        "Sicilian Defense: Najdorf Variation"
+    );
+
+    let family_only = OpeningName::parse("Italian Game");
+    assert_eq!(family_only.variation, None);
+    assert_eq!(family_only.at(OpeningLevel::SubVariation), "Italian Game");
+}
+
+#[test]
+fn sums_expected_scores_of_rated_games() {
+    let pgn = "[White \"alice\"]\n[Black \"bob\"]\n[WhiteElo \"1900\"]\n[BlackElo \"1500\"]\n[Result \"1/2-1/2\"]\n\n1. e4 e5 2. Nf3 Nc6 3. Bc4 1/2-1/2\n\n\
+               [White \"bob\"]\n[Black \"alice\"]\n[WhiteElo \"?\"]\n[BlackElo \"1900\"]\n[Result \"0-1\"]\n\n1. e4 e5 2. Nf3 Nc6 3. Bc4 0-1\n\n";
+    let classifier = OpeningClassifier::new(openings());
+    let mut counter = OpeningCounter::new(&classifier, "alice".to_string(), None);
+    let mut reader = BufferedReader::new_cursor(pgn.as_bytes());
+
+    while reader.read_game(&mut counter).unwrap().is_some() {}
+
+    let italian = counter.openings["Italian Game"];
+    assert_eq!(italian.total(), 2);
+    assert_eq!(italian.rated, 1);
+    assert_eq!(italian.score(), 0.75);
+    assert!((italian.expected_score().unwrap() - 0.909).abs() < 1e-3);
+}
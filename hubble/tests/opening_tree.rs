@@ -0,0 +1,127 @@
+use hubble::analysis::opening_tree::{common_moves, start_ply, start_position, OpeningTree};
+use shakmaty::{fen, uci::Uci, Chess, Color, Outcome, Position};
+
+const PGN: &str = r#"[Result "1-0"]
+
+1. e4 e5 2. Nf3 Nc6 3. Bc4 1-0
+
+[Result "0-1"]
+
+1. e4 c5 2. Nf3 0-1
+
+[Result "1/2-1/2"]
+
+1. Nf3 Nc6 2. e4 e5 3. Bb5 1/2-1/2
+
+[Result "*"]
+
+1. d4 *
+"#;
+
+fn play(moves: &[&str]) -> Chess {
+    let mut pos = Chess::default();
+    for mv in moves {
+        let m = mv.parse::<Uci>().unwrap().to_move(&pos).unwrap();
+        pos.play_unchecked(&m);
+    }
+    pos
+}
+
+#[test]
+fn counts_moves_and_results() {
+    let tree = OpeningTree::from_reader(PGN.as_bytes(), 10, 1).unwrap();
+    let start = tree.moves(&Chess::default());
+    let e4 = start
+        .iter()
+        .find(|entry| entry.uci().to_string() == "e2e4")
+        .unwrap();
+
+    assert_eq!(tree.games(), 4);
+    assert_eq!(start.len(), 3);
+    assert_eq!((e4.n, e4.white, e4.draws, e4.black), (2, 1, 0, 1));
+}
+
+#[test]
+fn transpositions_share_a_position() {
+    let tree = OpeningTree::from_reader(PGN.as_bytes(), 10, 1).unwrap();
+    let entries = tree.moves(&play(&["g1f3", "b8c6", "e2e4", "e7e5"]));
+    let moves = entries
+        .iter()
+        .map(|entry| entry.uci().to_string())
+        .collect::<Vec<_>>();
+
+    assert_eq!(moves, vec!["f1c4", "f1b5"]);
+    assert_eq!(entries[0].white, 1);
+    assert_eq!(entries[1].draws, 1);
+}
+
+#[test]
+fn stops_at_max_ply() {
+    let tree = OpeningTree::from_reader(PGN.as_bytes(), 1, 1).unwrap();
+
+    assert_eq!(tree.positions(), 1);
+    assert!(tree.moves(&play(&["e2e4"])).is_empty());
+}
+
+#[test]
+fn prune_drops_rare_moves() {
+    let mut tree = OpeningTree::with_max_ply(4);
+    let moves = ["e2e4", "e7e5"].map(str::to_string);
+    tree.add_uci_game(
+        &moves,
+        Some(Outcome::Decisive {
+            winner: Color::White,
+        }),
+    );
+    tree.add_uci_game(&moves, Some(Outcome::Draw));
+    tree.add_uci_game(&["d2d4".to_string()], None);
+    tree.prune(2);
+
+    let start = tree.moves(&Chess::default());
+    assert_eq!(start.len(), 1);
+    assert_eq!(start[0].uci().to_string(), "e2e4");
+    assert_eq!(tree.moves(&play(&["e2e4"]))[0].n, 2);
+}
+
+#[test]
+fn prunes_rare_moves_while_reading() {
+    let tree = OpeningTree::from_reader(PGN.as_bytes(), 10, 2).unwrap();
+    let start = tree.moves(&Chess::default());
+
+    assert_eq!(tree.games(), 4);
+    assert_eq!(start.len(), 1);
+    assert_eq!(start[0].uci().to_string(), "e2e4");
+    assert!(tree.moves(&play(&["e2e4"])).is_empty());
+}
+
+#[test]
+fn lists_top_continuations_along_the_most_popular_line() {
+    let tree = OpeningTree::from_reader(PGN.as_bytes(), 10, 1).unwrap();
+    let plies = common_moves(&tree, &Chess::default(), 3, 2);
+
+    assert_eq!(plies.len(), 3);
+    assert_eq!(plies[0].ply, 1);
+    let first = plies[0]
+        .moves
+        .iter()
+        .map(|mv| (mv.san.as_str(), mv.games))
+        .collect::<Vec<_>>();
+    assert_eq!(first, vec![("e4", 2), ("Nf3", 1)]);
+    assert!((plies[0].moves[0].frequency - 0.5).abs() < 1e-9);
+    assert_eq!(plies[1].line, vec!["e4"]);
+    assert_eq!(plies[2].line, vec!["e4", "e5"]);
+    assert_eq!(plies[2].moves[0].san, "Nf3");
+}
+
+#[test]
+fn starts_from_a_move_list_or_fen() {
+    let moves = ["e4", "e7e5"].map(str::to_string);
+    let pos = start_position(None, &moves).unwrap();
+
+    assert_eq!(start_ply(&pos), 2);
+    assert_eq!(fen::epd(&pos), fen::epd(&play(&["e2e4", "e7e5"])));
+    let start = "rnbqkbnr/pppp1ppp/8/4p3/4P3/8/PPPP1PPP/RNBQKBNR w KQkq - 0 2";
+    let from_fen = start_position(Some(start), &[]).unwrap();
+    assert_eq!(fen::epd(&from_fen), fen::epd(&pos));
+    assert!(start_position(None, &["e5".to_string()]).is_err());
+}
@@ -0,0 +1,127 @@
+mod common;
+
+use common::{line, position};
+use hubble::analysis::phase::{divide, group_blunders, Division, Phase, PhaseOptions};
+use shakmaty::Chess;
+
+#[test]
+fn short_games_stay_in_the_opening() {
+    //Scholar's mate
+    let moves = line("e2e4 e7e5 f1c4 b8c6 d1h5 g8f6 h5f7");
+    let division = divide(&Chess::default(), &moves, &PhaseOptions::default()).unwrap();
+
+    assert_eq!(division, Division::default());
+}
+
+#[test]
+fn trading_queens_starts_the_middlegame() {
+    //Ruy Lopez exchange variation, the middlegame starts once the queens are off
+    let moves = line("e2e4 e7e5 g1f3 b8c6 f1b5 a7a6 b5c6 d7c6 d2d4 e5d4 d1d4 d8d4 f3d4 c8d7");
+    let division = divide(&Chess::default(), &moves, &PhaseOptions::default()).unwrap();
+
+    assert_eq!(
+        division,
+        Division {
+            middle_game: Some(12),
+            end_game: None,
+        }
+    );
+}
+
+#[test]
+fn divides_a_full_game_like_lichess() {
+    //Morphy - Duke of Brunswick and Count Isouard, Paris 1858. Lichess' divider puts the
+    //middlegame at ply 17 (9. Bg5 leaves three white pieces on the back rank) and the endgame
+    //at ply 32 (16... Nxb8), counted in plies played, one more than our move indexes
+    let moves = line(
+        "e2e4 e7e5 g1f3 d7d6 d2d4 c8g4 d4e5 g4f3 d1f3 d6e5 f1c4 g8f6 f3b3 d8e7 b1c3 c7c6 c1g5 \
+         b7b5 c3b5 c6b5 c4b5 b8d7 e1c1 a8d8 d1d7 d8d7 h1d1 e7e6 b5d7 f6d7 b3b8 d7b8 d1d8",
+    );
+    let lichess = PhaseOptions {
+        end_game_material: 0,
+        ..PhaseOptions::default()
+    };
+    let expected = Division {
+        middle_game: Some(16),
+        end_game: Some(31),
+    };
+
+    assert_eq!(divide(&Chess::default(), &moves, &lichess), Some(expected));
+    assert_eq!(
+        divide(&Chess::default(), &moves, &PhaseOptions::default()),
+        Some(expected)
+    );
+}
+
+#[test]
+fn custom_positions_start_in_their_phase() {
+    let start = position("2b1k2r/8/8/3n4/8/8/8/R2NKB1R w - - 0 1");
+    let division = divide(&start, &line("e1e2 e8e7 h1h8"), &PhaseOptions::default()).unwrap();
+
+    assert_eq!(
+        division,
+        Division {
+            middle_game: Some(0),
+            end_game: Some(2),
+        }
+    );
+}
+
+#[test]
+fn games_from_a_custom_position_do_not_replay_from_the_start() {
+    //The rook move is legal in the custom position but not from the standard start
+    let moves = line("e1e2 e8e7 h1h8");
+    assert_eq!(
+        divide(&Chess::default(), &moves, &PhaseOptions::default()),
+        None
+    );
+
+    //Moves that are fine from the start still have to replay up to the last one
+    let moves = line("e2e4 e7e5 g1f3 h1h8");
+    assert_eq!(
+        divide(&Chess::default(), &moves, &PhaseOptions::default()),
+        None
+    );
+}
+
+#[test]
+fn little_material_is_an_endgame() {
+    //Eight minor pieces are too many for the piece count but worth only 24
+    let start = position("2b1kn2/8/8/8/3nn3/8/8/2BNKN1N w - - 0 1");
+
+    let division = divide(&start, &[], &PhaseOptions::default()).unwrap();
+    assert_eq!(division.end_game, Some(0));
+
+    let options = PhaseOptions {
+        end_game_material: 0,
+        ..PhaseOptions::default()
+    };
+    let division = divide(&start, &[], &options).unwrap();
+    assert_eq!(
+        division,
+        Division {
+            middle_game: Some(0),
+            end_game: None,
+        }
+    );
+}
+
+#[test]
+fn groups_blunders_by_the_phases_reached() {
+    let division = Division {
+        middle_game: Some(10),
+        end_game: None,
+    };
+    assert_eq!(division.phase(9), Phase::Opening);
+    assert_eq!(division.phase(40), Phase::MiddleGame);
+
+    let grouped = group_blunders(&[3, 12, 40], &division);
+    assert_eq!(grouped.opening, vec![3]);
+    assert_eq!(grouped.middle_game, vec![12, 40]);
+    assert!(grouped.end_game.is_empty());
+
+    //A game that never left the opening keeps its blunders there
+    let grouped = group_blunders(&[3, 12], &Division::default());
+    assert_eq!(grouped.opening, vec![3, 12]);
+    assert!(grouped.end_game.is_empty());
+}
@@ -0,0 +1,60 @@
+mod common;
+
+use common::GameBuilder;
+use hubble::analysis::opening_tree::OpeningTree;
+use hubble::analysis::preparation::{
+    blunder_profile, frequent_lines, BlunderPhase, PreparationOptions,
+};
+
+#[test]
+fn lists_most_frequent_lines_first() {
+    let mut tree = OpeningTree::with_max_ply(2);
+    for line in [
+        "e2e4 e7e5",
+        "e2e4 e7e5",
+        "e2e4 c7c5",
+        "d2d4 d7d5",
+        "d2d4 d7d5",
+        "d2d4 d7d5",
+    ] {
+        tree.add_uci_game(&common::line(line), None);
+    }
+    let lines = frequent_lines(&tree, &PreparationOptions::default());
+
+    assert_eq!(lines.len(), 2);
+    assert_eq!(lines[0].moves, vec!["d4", "d5"]);
+    assert_eq!(lines[0].games, 3);
+    assert_eq!(lines[1].moves, vec!["e4", "e5"]);
+    assert_eq!(lines[1].games, 2);
+}
+
+#[test]
+fn counts_only_the_players_blunders() {
+    let games = vec![
+        //Even plies are white's moves
+        GameBuilder::new()
+            .players("carol", "dave")
+            .blunders(&[4, 5], &[20], &[])
+            .build(),
+        GameBuilder::new()
+            .players("dave", "carol")
+            .blunders(&[], &[21, 23, 30], &[])
+            .build(),
+    ];
+    let profile = blunder_profile(&games, "carol");
+
+    assert_eq!(profile.opening, 1);
+    assert_eq!(profile.middle_game, 3);
+    assert_eq!(profile.end_game, 0);
+    assert_eq!(profile.typical, Some(BlunderPhase::MiddleGame));
+}
+
+#[test]
+fn no_typical_phase_without_blunders() {
+    let games = vec![GameBuilder::new()
+        .players("carol", "dave")
+        .blunders(&[5], &[], &[])
+        .build()];
+
+    assert_eq!(blunder_profile(&games, "carol").typical, None);
+}
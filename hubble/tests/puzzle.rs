@@ -0,0 +1,93 @@
+mod common;
+
+use common::GameBuilder;
+use hubble::analysis::puzzle::{
+    blunder_positions, is_unique_solution, puzzle_from_search, puzzles_to_csv,
+};
+use hubble::pgn::puzzles_to_pgn;
+use hubble_db::models::game::Game;
+
+fn game() -> Game {
+    GameBuilder::new()
+        .id("abcdefgh")
+        .players("alice", "bob")
+        .ratings(Some(1800), None)
+        .moves("e2e4 e7e5 g1f3 b8c6 f1c4 g8f6")
+        .blunders(&[2, 5], &[], &[])
+        .build()
+}
+
+#[test]
+fn finds_positions_before_own_blunders() {
+    let game = game();
+
+    let positions = blunder_positions(&game, "alice");
+    assert_eq!(positions.len(), 1);
+    assert_eq!(positions[0].ply, 2);
+    assert!(positions[0].previous.is_some());
+
+    let positions = blunder_positions(&game, "bob");
+    assert_eq!(positions.len(), 1);
+    assert_eq!(positions[0].ply, 5);
+
+    assert!(blunder_positions(&game, "carol").is_empty());
+}
+
+#[test]
+fn solutions_have_to_win_by_a_margin() {
+    assert!(is_unique_solution(400, Some(0)));
+    assert!(is_unique_solution(99_999, Some(300)));
+    assert!(!is_unique_solution(400, Some(300)));
+    assert!(!is_unique_solution(50, Some(-400)));
+    assert!(!is_unique_solution(2000, Some(1500)));
+    assert!(!is_unique_solution(400, None));
+}
+
+#[test]
+fn builds_puzzles_from_the_search() {
+    let game = game();
+    let positions = blunder_positions(&game, "alice");
+    let line = vec!["d2d4".to_string(), "e5d4".to_string()];
+
+    let puzzle = puzzle_from_search(&game, "alice", &positions[0], 500, &line, Some(0)).unwrap();
+    assert_eq!(puzzle.ply, 2);
+    assert_eq!(
+        puzzle.fen,
+        "rnbqkbnr/pppp1ppp/8/4p3/4P3/8/PPPP1PPP/RNBQKBNR w KQkq - 0 2"
+    );
+    assert_eq!(puzzle.previous_move.as_deref(), Some("e7e5"));
+    assert_eq!(puzzle.solution, vec!["d2d4"]);
+    assert_eq!(puzzle.played, "g1f3");
+    assert_eq!(puzzle.themes, vec!["opening", "oneMove"]);
+    assert_eq!(puzzle.rating, Some(1800));
+
+    //Stored under the same name however the player was spelled
+    let positions = blunder_positions(&game, "Alice");
+    let puzzle = puzzle_from_search(&game, "Alice", &positions[0], 500, &line, Some(0)).unwrap();
+    assert_eq!(puzzle.player, "alice");
+
+    let line = vec!["g1f3".to_string()];
+    assert!(puzzle_from_search(&game, "alice", &positions[0], 500, &line, Some(0)).is_none());
+}
+
+#[test]
+fn exports_lichess_csv_and_pgn() {
+    let game = game();
+    let positions = blunder_positions(&game, "alice");
+    let line = vec!["d2d4".to_string()];
+    let puzzle = puzzle_from_search(&game, "alice", &positions[0], 500, &line, Some(0)).unwrap();
+
+    let csv = puzzles_to_csv(std::slice::from_ref(&puzzle));
+    let rows = csv.lines().collect::<Vec<_>>();
+    assert_eq!(
+        rows,
+        vec![
+            "PuzzleId,FEN,Moves,Rating,RatingDeviation,Popularity,NbPlays,Themes,GameUrl,OpeningTags",
+            "abcdefgh-2,rnbqkbnr/pppppppp/8/8/4P3/8/PPPP1PPP/RNBQKBNR b KQkq - 0 1,e7e5 d2d4,1800,500,0,0,opening oneMove,https://lichess.org/abcdefgh#2,",
+        ]
+    );
+
+    let pgn = puzzles_to_pgn(&[puzzle]);
+    assert!(pgn.contains("[FEN \"rnbqkbnr/pppp1ppp/8/4p3/4P3/8/PPPP1PPP/RNBQKBNR w KQkq - 0 2\"]"));
+    assert!(pgn.contains("2. d4 {Nf3 was played in the game} *"));
+}
@@ -0,0 +1,135 @@
+mod common;
+
+use common::GameBuilder;
+use hubble::analysis::gaps::{find_gaps, GapOptions, GapReason};
+use hubble::analysis::opening_tree::OpeningTree;
+use hubble::analysis::repertoire::build_repertoire;
+use hubble::pgn::repertoire_to_pgn;
+use hubble_db::models::game::Game;
+use hubble_db::models::game_filter::Color;
+
+fn game(moves: &str, winner: Option<&str>) -> Game {
+    GameBuilder::new()
+        .players("alice", "bob")
+        .ratings(None, Some(1500))
+        .winner(winner)
+        .moves(moves)
+        .even_scores()
+        .build()
+}
+
+fn games() -> Vec<Game> {
+    vec![
+        game("e2e4 e7e5 g1f3", Some("alice")),
+        game("e2e4 e7e5 g1f3", None),
+        game("e2e4 c7c5 g1f3", Some("bob")),
+        game("d2d4 d7d5", Some("alice")),
+    ]
+}
+
+#[test]
+fn counts_moves_and_results_per_position() {
+    let repertoire = build_repertoire(&games(), "alice", Color::White, 10);
+    let start = &repertoire.positions["rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq -"];
+
+    assert_eq!(repertoire.games, 4);
+    assert_eq!(start[0].san, "e4");
+    assert_eq!(start[0].games, 3);
+    assert_eq!((start[0].won, start[0].drawn, start[0].lost), (1, 1, 1));
+    assert_eq!(start[0].avg_opponent_rating, Some(1500.));
+    assert_eq!(start[1].san, "d4");
+}
+
+#[test]
+fn skips_games_with_the_other_color() {
+    let repertoire = build_repertoire(&games(), "alice", Color::Black, 10);
+
+    assert_eq!(repertoire.games, 0);
+    assert!(repertoire.positions.is_empty());
+}
+
+#[test]
+fn exports_most_played_line_as_mainline() {
+    let repertoire = build_repertoire(&games(), "alice", Color::White, 10);
+    let pgn = repertoire_to_pgn(&repertoire, 1, 10);
+
+    assert!(pgn.contains("[Event \"Repertoire of alice as white\"]"));
+    assert!(pgn.contains("1. e4 {3 games: +1 =1 -1, avg eval +0.00} ( 1. d4"));
+    assert!(pgn.contains("1... e5 {2 games: +1 =1 -0, avg eval +0.00} ( 1... c5"));
+    assert!(pgn.trim_end().ends_with('*'));
+}
+
+#[test]
+fn prunes_rare_moves() {
+    let repertoire = build_repertoire(&games(), "alice", Color::White, 10);
+    let pgn = repertoire_to_pgn(&repertoire, 2, 10);
+
+    assert!(!pgn.contains("d4"));
+    assert!(!pgn.contains("c5"));
+}
+
+fn reference() -> OpeningTree {
+    let mut tree = OpeningTree::with_max_ply(10);
+    for line in [
+        "e7e5 g1f3",
+        "e7e5 g1f3",
+        "c7c5 g1f3",
+        "c7c5 g1f3",
+        "e7e6 d2d4",
+    ] {
+        tree.add_uci_game(&common::line(&format!("e2e4 {}", line)), None);
+    }
+    tree
+}
+
+#[test]
+fn finds_replies_the_player_never_faced() {
+    let repertoire = build_repertoire(&games(), "alice", Color::White, 10);
+    let options = GapOptions {
+        min_games: 1,
+        min_score: 0.,
+        ..GapOptions::default()
+    };
+    let gaps = find_gaps(&repertoire, &reference(), options);
+
+    assert_eq!(gaps.len(), 1);
+    assert_eq!(gaps[0].line, vec!["e4"]);
+    assert_eq!(gaps[0].reply, "e6");
+    assert_eq!(gaps[0].reasons, vec![GapReason::NeverFaced]);
+}
+
+#[test]
+fn ranks_gaps_by_how_often_they_are_faced() {
+    let repertoire = build_repertoire(&games(), "alice", Color::White, 10);
+    let gaps = find_gaps(&repertoire, &reference(), GapOptions::default());
+    let replies = gaps
+        .iter()
+        .map(|gap| gap.reply.as_str())
+        .collect::<Vec<_>>();
+
+    assert_eq!(replies.len(), 3);
+    assert_eq!(replies[2], "e6");
+    assert_eq!(gaps[0].reasons, vec![GapReason::FewGames]);
+    //Faced in 2 of 5 reference games after e4, which alice plays in 3 of her 4 games
+    assert!((gaps[0].frequency - 0.3).abs() < 1e-9);
+}
+
+#[test]
+fn adds_up_transposed_lines() {
+    let games = vec![game("e2e4 e7e6 d2d4", None), game("d2d4 e7e6 e2e4", None)];
+    let repertoire = build_repertoire(&games, "alice", Color::White, 10);
+    let mut reference = OpeningTree::with_max_ply(10);
+    for line in ["e2e4 e7e6 d2d4 d7d5", "d2d4 e7e6 e2e4 d7d5"] {
+        reference.add_uci_game(&common::line(line), None);
+    }
+    let options = GapOptions {
+        min_games: 1,
+        min_score: 0.,
+        ..GapOptions::default()
+    };
+    let gaps = find_gaps(&repertoire, &reference, options);
+
+    assert_eq!(gaps.len(), 1);
+    assert_eq!(gaps[0].reply, "d5");
+    assert!((gaps[0].frequency - 1.).abs() < 1e-9);
+}
@@ -0,0 +1,95 @@
+mod common;
+
+use common::GameBuilder;
+use hubble::analysis::stats::{
+    expected_score, performance_rating, player_performance, wilson_interval, PerformanceGroup, Z_95,
+};
+use std::collections::HashMap;
+
+fn close(a: f64, b: f64) -> bool {
+    (a - b).abs() < 1e-3
+}
+
+#[test]
+fn expected_score_follows_elo() {
+    assert!(close(expected_score(1500., 1500.), 0.5));
+    assert!(close(expected_score(1900., 1500.), 0.909));
+    assert!(close(
+        expected_score(1500., 1900.) + expected_score(1900., 1500.),
+        1.
+    ));
+}
+
+#[test]
+fn wilson_interval_narrows_with_games() {
+    let (few_lower, few_upper) = wilson_interval(1., 3, Z_95);
+    let (many_lower, many_upper) = wilson_interval(0.8, 100, Z_95);
+
+    assert!(close(few_lower, 0.438));
+    assert!(close(few_upper, 1.));
+    assert!(close(many_lower, 0.711));
+    assert!(close(many_upper, 0.867));
+    //Three straight wins are less convincing than 80% over a hundred games
+    assert!(few_lower < many_lower);
+
+    assert_eq!(wilson_interval(0., 0, Z_95), (0., 1.));
+}
+
+#[test]
+fn performance_rating_inverts_expected_score() {
+    assert!(close(performance_rating(1500., 0.5), 1500.));
+    assert!(close(
+        performance_rating(1500., expected_score(1700., 1500.)),
+        1700.
+    ));
+    assert!(close(performance_rating(1500., 1.), 2300.));
+    assert!(close(performance_rating(1500., 0.), 700.));
+}
+
+#[test]
+fn groups_performance_by_color_and_month() {
+    let games = vec![
+        GameBuilder::new()
+            .players("carol", "dave")
+            .winner(Some("carol"))
+            .ratings(Some(1500), Some(1500))
+            .played_in(2022, 1)
+            .build(),
+        GameBuilder::new()
+            .players("carol", "erin")
+            .winner(None)
+            .ratings(Some(1500), Some(1700))
+            .played_in(2022, 1)
+            .build(),
+        GameBuilder::new()
+            .players("dave", "carol")
+            .winner(Some("dave"))
+            .ratings(Some(1500), Some(1500))
+            .played_in(2022, 2)
+            .build(),
+        GameBuilder::new()
+            .players("dave", "erin")
+            .winner(Some("dave"))
+            .ratings(Some(1500), Some(1500))
+            .played_in(2022, 2)
+            .build(),
+    ];
+    let openings = HashMap::new();
+
+    let colors = player_performance(&games, "carol", PerformanceGroup::Color, &openings);
+    assert_eq!(colors.len(), 2);
+    assert_eq!(colors[0].group, "white");
+    assert_eq!(colors[0].games, 2);
+    assert_eq!(colors[0].score, 0.75);
+    assert!(close(colors[0].average_opponent.unwrap(), 1600.));
+    assert!(close(
+        colors[0].expected_score.unwrap(),
+        (0.5 + expected_score(1500., 1700.)) / 2.
+    ));
+    assert_eq!(colors[1].group, "black");
+    assert_eq!(colors[1].performance, Some(700.));
+
+    let months = player_performance(&games, "carol", PerformanceGroup::Month, &openings);
+    let months = months.iter().map(|m| m.group.as_str()).collect::<Vec<_>>();
+    assert_eq!(months, vec!["2022-01", "2022-02"]);
+}
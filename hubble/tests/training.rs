@@ -0,0 +1,180 @@
+mod common;
+
+use chrono::{NaiveDate, NaiveDateTime};
+use common::{line, GameBuilder};
+use hubble::analysis::repertoire::build_repertoire;
+use hubble::training::{
+    check_answer, merge_items, puzzle_items, recall_quality, repertoire_items, review,
+    TrainingError,
+};
+use hubble_db::models::game_filter::Color;
+use hubble_db::models::puzzle::Puzzle;
+use hubble_db::models::training::{Schedule, TrainingItem};
+
+fn at(day: u32, hour: u32) -> NaiveDateTime {
+    NaiveDate::from_ymd_opt(2022, 5, day)
+        .and_then(|date| date.and_hms_opt(hour, 0, 0))
+        .unwrap()
+}
+
+fn item(fen: &str, answers: &[&str]) -> TrainingItem {
+    TrainingItem {
+        id: 1,
+        player: "alice".to_string(),
+        kind: "repertoire".to_string(),
+        fen: fen.to_string(),
+        answers: answers.iter().map(|answer| answer.to_string()).collect(),
+        game_id: None,
+        repetitions: 0,
+        interval_days: 0,
+        ease: 2.5,
+        due_at: at(1, 0),
+    }
+}
+
+#[test]
+fn schedules_like_sm2() {
+    let now = at(1, 12);
+    let start = item("", &[]).schedule();
+
+    let first = review(&start, 5, now);
+    assert_eq!((first.repetitions, first.interval_days), (1, 1));
+    assert!((first.ease - 2.6).abs() < 1e-9);
+    assert_eq!(first.due_at, at(2, 12));
+
+    let second = review(&first, 5, now);
+    assert_eq!((second.repetitions, second.interval_days), (2, 6));
+
+    let third = review(&second, 4, now);
+    assert_eq!((third.repetitions, third.interval_days), (3, 16));
+    assert!((third.ease - 2.7).abs() < 1e-9);
+
+    let failed = review(&third, 1, now);
+    assert_eq!((failed.repetitions, failed.interval_days), (0, 1));
+    assert!((failed.ease - 2.16).abs() < 1e-9);
+}
+
+#[test]
+fn ease_has_a_floor() {
+    let now = at(1, 12);
+    let mut schedule = Schedule {
+        repetitions: 0,
+        interval_days: 0,
+        ease: 1.4,
+        due_at: now,
+    };
+    for _ in 0..3 {
+        schedule = review(&schedule, 0, now);
+    }
+    assert!((schedule.ease - 1.3).abs() < 1e-9);
+}
+
+#[test]
+fn grades_answers_by_time() {
+    assert_eq!(recall_quality(false, 1000), 1);
+    assert_eq!(recall_quality(true, 5000), 5);
+    assert_eq!(recall_quality(true, 20_000), 4);
+    assert_eq!(recall_quality(true, 60_000), 3);
+}
+
+#[test]
+fn checks_moves_in_uci_and_san() {
+    let start = item(
+        "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1",
+        &["e2e4"],
+    );
+    assert_eq!(
+        check_answer(&start, "e2e4").unwrap(),
+        ("e2e4".to_string(), true)
+    );
+    assert_eq!(
+        check_answer(&start, "e4").unwrap(),
+        ("e2e4".to_string(), true)
+    );
+    assert_eq!(
+        check_answer(&start, "Nf3").unwrap(),
+        ("g1f3".to_string(), false)
+    );
+    assert!(matches!(
+        check_answer(&start, "e5"),
+        Err(TrainingError::IllegalMove)
+    ));
+
+    let castle = item("r3k2r/8/8/8/8/8/8/R3K2R w KQkq -", &["e1g1"]);
+    assert_eq!(
+        check_answer(&castle, "O-O").unwrap(),
+        ("e1g1".to_string(), true)
+    );
+
+    assert!(matches!(
+        check_answer(&item("not a fen", &[]), "e4"),
+        Err(TrainingError::InvalidPosition)
+    ));
+}
+
+#[test]
+fn repertoire_items_are_the_players_common_moves() {
+    let game = GameBuilder::new()
+        .players("alice", "bob")
+        .moves("e2e4 e7e5 g1f3 b8c6")
+        .build();
+    let mut rare = game.clone();
+    rare.moves = line("d2d4");
+    let games = vec![game.clone(), game, rare];
+
+    //Stored under the same name however the player was spelled
+    let repertoire = build_repertoire(&games, "Alice", Color::White, 10);
+    let items = repertoire_items(&repertoire, 2);
+    assert!(items.iter().all(|item| item.player == "alice"));
+    let items = items
+        .iter()
+        .map(|item| (item.fen.as_str(), item.answers.clone()))
+        .collect::<Vec<_>>();
+
+    assert_eq!(
+        items,
+        vec![
+            (
+                "rnbqkbnr/pppp1ppp/8/4p3/4P3/8/PPPP1PPP/RNBQKBNR w KQkq -",
+                vec!["g1f3".to_string()]
+            ),
+            (
+                "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq -",
+                vec!["e2e4".to_string()]
+            ),
+        ]
+    );
+}
+
+fn puzzle(game_id: &str, fen: &str, solution: &str) -> Puzzle {
+    Puzzle {
+        game_id: game_id.to_string(),
+        ply: 4,
+        player: "alice".to_string(),
+        fen: fen.to_string(),
+        previous_fen: None,
+        previous_move: None,
+        solution: vec![solution.to_string()],
+        played: "a2a3".to_string(),
+        best_score: 300,
+        second_score: Some(0),
+        themes: Vec::new(),
+        rating: None,
+    }
+}
+
+#[test]
+fn merges_puzzles_from_the_same_position() {
+    //The same blunder in two games reaches the same fen, counters included
+    let fen = "r1bqkbnr/pppp1ppp/2n5/4p3/4P3/5N2/PPPP1PPP/RNBQKB1R w KQkq - 2 3";
+    let puzzles = vec![
+        puzzle("game1", fen, "f1b5"),
+        puzzle("game2", fen, "f1c4"),
+        puzzle("game3", fen, "f1b5"),
+    ];
+    let items = merge_items(puzzle_items("alice", puzzles));
+
+    assert_eq!(items.len(), 1);
+    assert_eq!(items[0].answers, vec!["f1b5", "f1c4"]);
+    assert_eq!(items[0].game_id.as_deref(), Some("game1"));
+}
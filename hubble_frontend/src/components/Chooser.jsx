@@ -9,7 +9,7 @@ export default function Chooser() {
 
   function getGames() {
     axios.get("/api/games").then((res) => {
-      setGames(res.data);
+      setGames(res.data.games);
     });
   }
 
@@ -1,61 +1,122 @@
+mod row;
+
+use std::collections::HashSet;
+use std::env;
 use std::fs::File;
-use std::io::prelude::*;
-use std::io::BufReader;
+use std::io::{BufRead, BufReader};
 
 use diesel::pg::PgConnection;
-use hubble_db::get_connection;
-use hubble_db::models::{insert_openings, Opening};
+use hubble_db::models::{upsert_openings, NewOpening};
+use row::{parse_row, Columns};
 
-fn format_row(row: &str) -> Vec<String> {
-    row.split("\t").map(str::to_string).collect()
-}
+const DEFAULT_FILES: [&str; 5] = [
+    "data/a.tsv",
+    "data/b.tsv",
+    "data/c.tsv",
+    "data/d.tsv",
+    "data/e.tsv",
+];
 
-fn open_file(filename: &str) -> Vec<Vec<String>> {
-    let mut contents = String::new();
-    let file = File::open(filename).unwrap();
-    let mut buf_reader = BufReader::new(file);
-    buf_reader.read_to_string(&mut contents).unwrap();
+// Keeps every insert well below the postgres limit on bind parameters
+const BATCH_SIZE: usize = 1000;
+
+#[derive(Debug, Default)]
+struct Summary {
+    rows: usize,
+    invalid: usize,
+    saved: usize,
+}
 
-    contents
-        .split("\n")
-        .map(format_row)
-        .collect::<Vec<Vec<String>>>()
+fn local_path(source: &str) -> &str {
+    //Downloaded files can be passed by their file:// url
+    source.strip_prefix("file://").unwrap_or(source)
 }
 
-fn insert_file(filename: &str, conn: &PgConnection, id: &mut i32) {
-    let data = open_file(filename);
+fn import_file(path: &str, conn: &PgConnection, summary: &mut Summary) -> Result<(), String> {
+    let file = File::open(path).map_err(|e| format!("could not open {}: {}", path, e))?;
+    let mut columns = Columns::default();
     let mut openings = Vec::new();
+    let mut seen = HashSet::new();
 
-    for row in data {
-        if row.len() < 3 || row[0] == "eco" {
+    for (idx, line) in BufReader::new(file).lines().enumerate() {
+        let line = line.map_err(|e| format!("could not read {}: {}", path, e))?;
+        if line.trim().is_empty() {
             continue;
         }
+        if idx == 0 {
+            if let Some(header) = Columns::from_header(&line) {
+                columns = header;
+                continue;
+            }
+        }
 
-        let eco = &row[0];
-        let name = &row[1];
-        let moves = &row[2];
-
-        openings.push(Opening::new(
-            *id,
-            eco.to_string(),
-            name.to_string(),
-            moves.to_string(),
-        ));
-        *id += 1;
+        summary.rows += 1;
+        match parse_row(&columns, &line) {
+            //Postgres refuses to upsert the same row twice in one statement
+            Ok(row) if !seen.insert((row.eco.clone(), row.name.clone(), row.pgn.clone())) => {}
+            Ok(row) => openings.push(NewOpening {
+                eco: row.eco,
+                name: row.name,
+                pgn: row.pgn,
+                ply: row.uci.len() as i32,
+                uci: row.uci.join(" "),
+                epd: row.epd,
+            }),
+            Err(e) => {
+                summary.invalid += 1;
+                eprintln!("{}:{}: {}", path, idx + 1, e);
+            }
+        }
     }
 
-    insert_openings(&conn, openings).unwrap();
+    for chunk in openings.chunks(BATCH_SIZE) {
+        summary.saved +=
+            upsert_openings(conn, chunk).map_err(|e| format!("could not save {}: {}", path, e))?;
+    }
+    Ok(())
 }
 
 fn main() {
     dotenv::from_filename("../.env").ok();
-    let conn = get_connection();
-    let files = ["a", "b", "c", "d", "e"];
-    let mut id = 0;
-
-    for f in files {
-        let filename = format!("data/{}.tsv", f);
-        println!("filename {}", &filename);
-        insert_file(&filename, &conn, &mut id);
+
+    let args = env::args().skip(1).collect::<Vec<_>>();
+    let files = if args.is_empty() {
+        DEFAULT_FILES.iter().map(|f| f.to_string()).collect()
+    } else {
+        args
+    };
+
+    let pool = match hubble_db::establish_connection() {
+        Ok(pool) => pool,
+        Err(e) => {
+            eprintln!("{}", e);
+            std::process::exit(1);
+        }
+    };
+    if let Err(e) = hubble_db::run_migrations(&pool) {
+        eprintln!("{}", e);
+        std::process::exit(1);
     }
+    let conn = match pool.get() {
+        Ok(conn) => conn,
+        Err(e) => {
+            eprintln!("{}", e);
+            std::process::exit(1);
+        }
+    };
+
+    let mut summary = Summary::default();
+    for source in &files {
+        let path = local_path(source);
+        println!("filename {}", path);
+        if let Err(e) = import_file(path, &conn, &mut summary) {
+            eprintln!("{}", e);
+            std::process::exit(1);
+        }
+    }
+
+    println!(
+        "{} rows, {} invalid, {} openings saved",
+        summary.rows, summary.invalid, summary.saved
+    );
 }
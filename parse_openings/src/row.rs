@@ -0,0 +1,106 @@
+use shakmaty::{fen, san::SanPlus, uci::Uci, CastlingMode, Chess, Position};
+
+// Column positions in a chess-openings tsv. Older files only have eco, name and pgn,
+// the generated ones add uci and epd.
+#[derive(Debug, Clone)]
+pub struct Columns {
+    eco: usize,
+    name: usize,
+    pgn: usize,
+    uci: Option<usize>,
+    epd: Option<usize>,
+}
+
+impl Default for Columns {
+    fn default() -> Self {
+        Self {
+            eco: 0,
+            name: 1,
+            pgn: 2,
+            uci: None,
+            epd: None,
+        }
+    }
+}
+
+impl Columns {
+    pub fn from_header(line: &str) -> Option<Self> {
+        let names = line.split('\t').map(str::trim).collect::<Vec<_>>();
+        let find = |column: &str| names.iter().position(|name| *name == column);
+
+        Some(Self {
+            eco: find("eco")?,
+            name: find("name")?,
+            pgn: find("pgn")?,
+            uci: find("uci"),
+            epd: find("epd"),
+        })
+    }
+}
+
+#[derive(Debug)]
+pub struct Row {
+    pub eco: String,
+    pub name: String,
+    pub pgn: String,
+    pub uci: Vec<String>,
+    pub epd: String,
+}
+
+pub fn parse_row(columns: &Columns, line: &str) -> Result<Row, String> {
+    let fields = line.split('\t').map(str::trim).collect::<Vec<_>>();
+    let field = |idx: usize| {
+        fields
+            .get(idx)
+            .filter(|value| !value.is_empty())
+            .map(|value| value.to_string())
+    };
+
+    let eco = field(columns.eco).ok_or("missing eco")?;
+    let name = field(columns.name).ok_or("missing name")?;
+    let pgn = field(columns.pgn).ok_or("missing pgn")?;
+
+    //Replaying the line is the only way to know the moves are legal
+    let mut pos = Chess::default();
+    let mut uci = Vec::new();
+    for token in pgn.split_whitespace() {
+        let san = token.trim_start_matches(|c: char| c.is_ascii_digit() || c == '.');
+        if san.is_empty() {
+            continue;
+        }
+        let m = san
+            .parse::<SanPlus>()
+            .map_err(|_| format!("invalid move {}", san))?
+            .san
+            .to_move(&pos)
+            .map_err(|_| format!("illegal move {}", san))?;
+        uci.push(Uci::from_move(&m, CastlingMode::Standard).to_string());
+        pos.play_unchecked(&m);
+    }
+    if uci.is_empty() {
+        return Err(String::from("no moves"));
+    }
+
+    if let Some(expected) = columns.uci.and_then(field) {
+        if expected
+            .split_whitespace()
+            .ne(uci.iter().map(String::as_str))
+        {
+            return Err(format!("uci {} does not match the pgn", expected));
+        }
+    }
+    let epd = fen::epd(&pos);
+    if let Some(expected) = columns.epd.and_then(field) {
+        if expected != epd {
+            return Err(format!("epd {} does not match the pgn", expected));
+        }
+    }
+
+    Ok(Row {
+        eco,
+        name,
+        pgn,
+        uci,
+        epd,
+    })
+}